@@ -1,9 +1,6 @@
 import { drawElectrostaticFieldLines, drawPotentialContours } from './fieldline.js';
-import { compute_field_electrostatic_direct_to_buffer, compute_field_magnetostatic_direct_to_buffer, 
-    compute_electric_field_dynamic_to_buffer, compute_field_electrostatic_per_charge_direct_to_buffer,
-    compute_field_magnetostatic_per_charge_direct_to_buffer, 
-    compute_one_force_electrostatic, compute_one_force_magnetostatic,
-    compute_potential_electrostatic_direct
+import { compute_one_force_electrostatic, compute_one_force_magnetostatic,
+    compute_potential_electrostatic_direct, SolverKind
 } from './maxwell/out/maxwell.js';
 
 export const chargeSize = 10;
@@ -49,7 +46,12 @@ export function getChargeFromPoint(charges, x, y, allowRadius, addChargeSize=tru
     return null;
 }
 
-function drawChargesOrCurrents(ctx, charges, computeField, selectedCharge, forces, dipoleMode) {
+function isMagnetostatic(solver) {
+    const kind = solver.get_kind();
+    return kind === SolverKind.MagnetostaticDirect || kind === SolverKind.MagnetostaticMultigrid;
+}
+
+function drawChargesOrCurrents(ctx, charges, solver, selectedCharge, forces, dipoleMode) {
     const width = ctx.canvas.clientWidth;
 
     const charges_no_test_charges = charges.filter(charge => !charge.isTestCharge);
@@ -81,7 +83,7 @@ function drawChargesOrCurrents(ctx, charges, computeField, selectedCharge, force
         });
     }
 
-    if (computeField === compute_field_magnetostatic_direct_to_buffer || computeField === compute_field_magnetostatic_per_charge_direct_to_buffer) {
+    if (isMagnetostatic(solver)) {
         drawCurrents(ctx, charges, selectedCharge);
     } else {
         drawCharges(ctx, charges, selectedCharge);
@@ -143,16 +145,16 @@ function logScale(x, minval, maxval) {
 }
 
 
-function drawTestChargeForces(ctx, charges, computeField, field, dipoleMode) {
+function drawTestChargeForces(ctx, charges, solver, field, dipoleMode) {
     charges.forEach(charge => {
         if(charge.isTestCharge) {
             let force;
             let fieldComputationFunction;
-            switch(computeField) {
-                case compute_field_electrostatic_direct_to_buffer:
+            switch(solver.get_kind()) {
+                case SolverKind.ElectrostaticDirect:
                     fieldComputationFunction = compute_one_force_electrostatic;
                     break;
-                case compute_field_magnetostatic_direct_to_buffer:
+                case SolverKind.MagnetostaticDirect:
                     fieldComputationFunction = compute_one_force_magnetostatic;
                     break;
                 default:
@@ -270,14 +272,14 @@ function drawCurrents(ctx, charges, selectedCharge) {
     });
 }
 
-function generateVectors(computeField, rect, charges, field) {
+function generateVectors(solver, perCharge, rect, charges, field) {
     const vectors = [];
     const step = 20;
     var buffer;
     var n_per_point;
     var color = 'black';
     
-    if(computeField === compute_field_electrostatic_per_charge_direct_to_buffer || computeField === compute_field_magnetostatic_per_charge_direct_to_buffer) {
+    if(perCharge) {
         buffer = new Float64Array(2*charges.length);
         n_per_point = charges.length;
     } else {
@@ -293,7 +295,11 @@ function generateVectors(computeField, rect, charges, field) {
                 const dy = y - charge.y;
                 return Math.sqrt(dx * dx + dy * dy) < step;
             })) continue;
-            computeField(field, x, y, buffer);
+            if(perCharge) {
+                solver.field_per_charge_to_buffer(field, x, y, buffer);
+            } else {
+                solver.field_to_buffer(field, x, y, buffer);
+            }
             for (let i = 0; i < n_per_point; i++) {
                 if (n_per_point > 1) {
                     color = charges[i].charge > 0 ? 'red' : 'blue';
@@ -387,7 +393,7 @@ export function drawArrow(ctx, x, y, u, v, color='black', linewidth=1, arrowLeng
     drawArrowHead(ctx, arrowHeadX, arrowHeadY, arrowHeadLength, angle, color);
 }
 
-export function draw(ctx, rect, charges, field, fieldVisType, computeField, showPotential, selectedCharge, forces, dipoleMode, extraDrawInfo) {
+export function draw(ctx, rect, charges, field, fieldVisType, solver, perCharge, showPotential, selectedCharge, forces, dipoleMode, extraDrawInfo) {
     ctx.clearRect(0, 0, rect.width, rect.height);
 
     const gaussianSurfacePoints = extraDrawInfo !== undefined ? extraDrawInfo['gaussianSurfacePoints'] : [];
@@ -421,14 +427,14 @@ export function draw(ctx, rect, charges, field, fieldVisType, computeField, show
     }
 
     if (fieldVisType === 'quiver') {
-        const vectors = generateVectors(computeField, rect, charges, field);
+        const vectors = generateVectors(solver, perCharge, rect, charges, field);
         drawQuiverPlot(ctx, vectors);
-    } else if (fieldVisType === 'fieldline' && computeField === compute_field_electrostatic_direct_to_buffer) {
+    } else if (fieldVisType === 'fieldline' && solver.get_kind() === SolverKind.ElectrostaticDirect) {
         const charges_no_test_charges = charges.filter(charge => !charge.isTestCharge);
         // The following algorithm only works when field lines start and end on charges, so perfect for the
         // electric case but not the magnetic case
         drawElectrostaticFieldLines(charges_no_test_charges, field, ctx, rect, chargeSize, gaussianSurfacePoints);
-    } else if (fieldVisType === 'fieldline' && computeField === compute_field_magnetostatic_direct_to_buffer) {
+    } else if (fieldVisType === 'fieldline' && solver.get_kind() === SolverKind.MagnetostaticDirect) {
         // Here we take cheeky advantage of the fact that the magnetostatic field lines are equivalent to
         // equipotential lines if we were solving an electrostatic problem. 
         let uniformField = field.get_uniform_field();
@@ -456,8 +462,8 @@ export function draw(ctx, rect, charges, field, fieldVisType, computeField, show
         console.error('Unknown field visualization type: ' + fieldVisType);
     }
 
-    drawTestChargeForces(ctx, charges, computeField, field, dipoleMode);
-    drawChargesOrCurrents(ctx, charges, computeField, selectedCharge, forces, dipoleMode);
+    drawTestChargeForces(ctx, charges, solver, field, dipoleMode);
+    drawChargesOrCurrents(ctx, charges, solver, selectedCharge, forces, dipoleMode);
 }
 
     
@@ -558,14 +558,14 @@ export function drawPotentialContours(charges, levels, ctx, color, show_directio
     let contours;
     if (show_direction) {
         let arrows;
-        [contours, arrows] = generate_potential_contours_and_arrow_positions_at_levels(charges, levels);
+        ({contours, arrows} = generate_potential_contours_and_arrow_positions_at_levels(charges, levels));
         for (let arrow of arrows) {
             const [x, y] = arrow;
             const B = compute_field_magnetostatic_direct(charges, x, y);
             drawDirectionArrow(x, y, B.u, B.v, ctx);
         }
     } else {
-        contours = generate_potential_contours_at_levels(charges, levels);
+        ({contours} = generate_potential_contours_at_levels(charges, levels));
     }
 
     for (let contour of contours) {
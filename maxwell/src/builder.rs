@@ -0,0 +1,211 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+use crate::FieldConfiguration;
+use crate::geometry::{BoundaryWidths, BoundaryCondition};
+use crate::multigrid::PotentialBoundary;
+use crate::softening::{SofteningKernel, ForceLaw};
+use crate::stencil::StencilOrder;
+use crate::units::UnitSystem;
+use crate::error::deserialize;
+
+/// The method used to find the fields
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SolverKind {
+    /// Coulomb's law summed over the sources
+    #[default]
+    ElectrostaticDirect,
+    /// The Biot-Savart law summed over the out-of-plane currents, wires and magnets
+    MagnetostaticDirect,
+    /// Poisson's equation solved on the grid by FFT
+    ElectrostaticFourier,
+    /// Maxwell's equations evolved on the grid
+    Dynamic,
+    /// Poisson's equation, with conductors and dielectrics, solved on the grid by multigrid
+    ElectrostaticMultigrid,
+    /// The vector potential of the currents, with magnetic materials, solved on the grid by multigrid
+    MagnetostaticMultigrid,
+}
+
+impl SolverKind {
+    pub fn uses_grid(&self) -> bool {
+        matches!(self, SolverKind::ElectrostaticFourier | SolverKind::Dynamic | SolverKind::ElectrostaticMultigrid | SolverKind::MagnetostaticMultigrid)
+    }
+
+    pub fn uses_multigrid(&self) -> bool {
+        matches!(self, SolverKind::ElectrostaticMultigrid | SolverKind::MagnetostaticMultigrid)
+    }
+
+    /// Maxwell's equations on the grid describe line charges, while the direct solvers default to point charges
+    pub fn default_force_law(&self) -> ForceLaw {
+        if self.uses_grid() {
+            ForceLaw::Logarithmic
+        } else {
+            ForceLaw::InverseSquare
+        }
+    }
+}
+
+/// A reason that a FieldConfiguration cannot be built
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigurationError {
+    /// The physical region must have a finite, positive size
+    InvalidExtent { x_max: f64, y_max: f64 },
+    /// The boundary cells take up the whole grid along an axis
+    NoInteriorCells { axis: char, cells: usize, boundary_cells: usize },
+    /// The grid is too small for the finite-difference stencils
+    TooFewCellsForStencil { axis: char, cells: usize, minimum: usize },
+    /// Absorbing boundaries for the dynamic solver need a damping layer on every side
+    MissingAbsorbingLayer { widths: BoundaryWidths },
+    /// The solver cannot use this force law
+    UnsupportedForceLaw { solver: SolverKind, law: ForceLaw },
+}
+
+impl fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigurationError::InvalidExtent { x_max, y_max } => {
+                write!(f, "the physical region must have a positive size, but is {} by {}", x_max, y_max)
+            },
+            ConfigurationError::NoInteriorCells { axis, cells, boundary_cells } => {
+                write!(f, "n{} = {} leaves no interior cells after {} boundary cells", axis, cells, boundary_cells)
+            },
+            ConfigurationError::TooFewCellsForStencil { axis, cells, minimum } => {
+                write!(f, "n{} = {} is too small for the chosen stencil order, which needs at least {} cells", axis, cells, minimum)
+            },
+            ConfigurationError::MissingAbsorbingLayer { widths } => {
+                write!(f, "absorbing boundaries need at least one boundary cell on every side, but the widths are {:?}", widths)
+            },
+            ConfigurationError::UnsupportedForceLaw { solver, law } => {
+                write!(f, "the {:?} solver cannot use the {:?} force law", solver, law)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationError {}
+
+/// Gathers the choices made when setting up a FieldConfiguration, and checks that they are consistent.
+///
+/// For example, from JS:
+/// `new FieldConfigurationBuilder(800, 600, 128, 96).boundary_width(16).solver(SolverKind.Dynamic).build()`
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct FieldConfigurationBuilder {
+    pub(crate) x_max: f64,
+    pub(crate) y_max: f64,
+    pub(crate) nx: usize,
+    pub(crate) ny: usize,
+    pub(crate) boundary: BoundaryWidths,
+    pub(crate) softening: SofteningKernel,
+    pub(crate) units: UnitSystem,
+    pub(crate) stencil_order: StencilOrder,
+    pub(crate) boundary_condition: BoundaryCondition,
+    pub(crate) potential_boundary: PotentialBoundary,
+    pub(crate) solver: SolverKind,
+    pub(crate) force_law: Option<ForceLaw>, // Defaults to the solver's preferred law
+}
+
+#[wasm_bindgen]
+impl FieldConfigurationBuilder {
+    /// Start from a physical region of x_max by y_max, covered by a grid of nx by ny cells including an eighth of
+    /// the cells as a boundary on each side
+    #[wasm_bindgen(constructor)]
+    pub fn new(x_max: f64, y_max: f64, nx: usize, ny: usize) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder {
+            x_max, y_max, nx, ny,
+            boundary: BoundaryWidths { x_low: nx / 8, x_high: nx / 8, y_low: ny / 8, y_high: ny / 8 },
+            softening: SofteningKernel::default(),
+            units: UnitSystem::default(),
+            stencil_order: StencilOrder::default(),
+            boundary_condition: BoundaryCondition::default(),
+            potential_boundary: PotentialBoundary::default(),
+            solver: SolverKind::default(),
+            force_law: None,
+        }
+    }
+
+    /// Use the same number of boundary cells on every side
+    pub fn boundary_width(self, width: usize) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { boundary: BoundaryWidths::uniform(width), ..self }
+    }
+
+    pub fn boundary_widths(self, x_low: usize, x_high: usize, y_low: usize, y_high: usize) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { boundary: BoundaryWidths { x_low, x_high, y_low, y_high }, ..self }
+    }
+
+    /// Set the softening kernel from a JS object such as `{type: "plummer", length: 2.0}`
+    pub fn softening(self, kernel: JsValue) -> Result<FieldConfigurationBuilder, JsError> {
+        Ok(self.softening_kernel(deserialize(kernel, "softening kernel")?))
+    }
+
+    pub fn units(self, units: UnitSystem) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { units, ..self }
+    }
+
+    pub fn stencil_order(self, stencil_order: StencilOrder) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { stencil_order, ..self }
+    }
+
+    pub fn boundary_condition(self, boundary_condition: BoundaryCondition) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { boundary_condition, ..self }
+    }
+
+    /// The condition at the outer edge of the grid for the multigrid solver, which also initialises the dynamic
+    /// solver when there are conductors or dielectrics
+    pub fn potential_boundary(self, potential_boundary: PotentialBoundary) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { potential_boundary, ..self }
+    }
+
+    pub fn solver(self, solver: SolverKind) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { solver, ..self }
+    }
+
+    pub fn force_law(self, force_law: ForceLaw) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { force_law: Some(force_law), ..self }
+    }
+
+    /// Check the choices and build the configuration, throwing a descriptive error if they are inconsistent
+    pub fn build(&self) -> Result<FieldConfiguration, JsError> {
+        self.try_build().map_err(|err| JsError::new(&err.to_string()))
+    }
+}
+
+impl FieldConfigurationBuilder {
+    pub fn softening_kernel(self, softening: SofteningKernel) -> FieldConfigurationBuilder {
+        FieldConfigurationBuilder { softening, ..self }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigurationError> {
+        if !(self.x_max > 0.0 && self.y_max > 0.0 && self.x_max.is_finite() && self.y_max.is_finite()) {
+            return Err(ConfigurationError::InvalidExtent { x_max: self.x_max, y_max: self.y_max });
+        }
+        for (axis, cells, boundary_cells) in [('x', self.nx, self.boundary.x_total()), ('y', self.ny, self.boundary.y_total())] {
+            if cells <= boundary_cells {
+                return Err(ConfigurationError::NoInteriorCells { axis, cells, boundary_cells });
+            }
+            let minimum = self.stencil_order.minimum_cells();
+            if cells < minimum {
+                return Err(ConfigurationError::TooFewCellsForStencil { axis, cells, minimum });
+            }
+        }
+        let widths = self.boundary;
+        // only the dynamic solver damps waves in the boundary layer
+        if self.solver == SolverKind::Dynamic && self.boundary_condition.is_absorbing() && [widths.x_low, widths.x_high, widths.y_low, widths.y_high].contains(&0) {
+            return Err(ConfigurationError::MissingAbsorbingLayer { widths });
+        }
+        // the multigrid solvers discretise Poisson's equation in the plane, which describes line charges
+        let law = self.force_law.unwrap_or(self.solver.default_force_law());
+        if self.solver.uses_multigrid() && law != ForceLaw::Logarithmic {
+            return Err(ConfigurationError::UnsupportedForceLaw { solver: self.solver, law });
+        }
+        Ok(())
+    }
+
+    pub fn try_build(&self) -> Result<FieldConfiguration, ConfigurationError> {
+        self.validate()?;
+        Ok(FieldConfiguration::from_builder(self))
+    }
+}
@@ -2,8 +2,13 @@ use wasm_bindgen::prelude::*;
 #[allow(unused)]
 use serde_wasm_bindgen::{from_value, to_value};
 use ndarray::Array2;
-use crate::{Pair, FieldConfiguration, Charge};
-use web_sys::console;
+use serde::Serialize;
+use std::cell::RefCell;
+use crate::{Pair, FieldConfiguration};
+use crate::logging::{self, Warning, Warnings};
+use crate::builder::SolverKind;
+use crate::multigrid::Region;
+use crate::images::ImageConductor;
 
 type PotentialCalculator = fn(&FieldConfiguration, f64, f64) -> f64;
 type PotentialGradientCalculator = fn(&FieldConfiguration, f64, f64) -> Pair;
@@ -11,7 +16,77 @@ type PotentialGradientCalculator = fn(&FieldConfiguration, f64, f64) -> Pair;
 struct ContouringCollection<'a> {
     potential_calculator: PotentialCalculator,
     potential_gradient_calculator: PotentialGradientCalculator,
-    configuration: &'a FieldConfiguration
+    configuration: &'a FieldConfiguration,
+    /// Equipotential regions with their potentials, at which their outlines are the contours
+    conductors: Vec<(&'a Region, f64)>,
+    /// Conductors represented by image charges, inside which the potential is flat
+    image_conductors: &'a [ImageConductor],
+    warnings: RefCell<Warnings>,
+}
+
+impl<'a> ContouringCollection<'a> {
+    fn new(potential_calculator: PotentialCalculator, potential_gradient_calculator: PotentialGradientCalculator,
+           configuration: &'a FieldConfiguration) -> ContouringCollection<'a> {
+        ContouringCollection { potential_calculator, potential_gradient_calculator, configuration, conductors: vec![], image_conductors: &[], warnings: RefCell::new(Warnings::default()) }
+    }
+
+    /// Contours of the electrostatic potential, from the grid solution if the configuration's solver is on the grid
+    /// and otherwise from the direct sum
+    fn electrostatic(configuration: &'a FieldConfiguration) -> ContouringCollection<'a> {
+        match configuration.solver {
+            SolverKind::ElectrostaticFourier => ContouringCollection::new(spectral_potential_or_0, spectral_field_or_0, configuration),
+            SolverKind::ElectrostaticMultigrid => ContouringCollection {
+                conductors: configuration.conductors.iter().map(|conductor| &conductor.region)
+                    .zip(configuration.conductor_potentials().unwrap_or_default()).collect(),
+                ..ContouringCollection::new(multigrid_potential_or_0, multigrid_field_or_0, configuration)
+            },
+            _ => ContouringCollection {
+                image_conductors: configuration.image_conductors(),
+                ..ContouringCollection::new(crate::compute_potential_electrostatic_direct, crate::compute_field_electrostatic_direct, configuration)
+            },
+        }
+    }
+
+    fn in_conductor(&self, x: f64, y: f64) -> bool {
+        self.conductors.iter().any(|(region, _)| region.contains(x, y))
+            || self.image_conductors.iter().any(|conductor| conductor.contains(x, y))
+    }
+}
+
+// Contours are only followed as far as the edge of the grid, so points beyond it can be treated as having no field
+fn spectral_potential_or_0(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    field_configuration.spectral_potential(x, y).unwrap_or(0.0)
+}
+
+fn spectral_field_or_0(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    field_configuration.spectral_field(x, y).unwrap_or(Pair { u: 0.0, v: 0.0 })
+}
+
+fn multigrid_potential_or_0(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    field_configuration.multigrid_potential(x, y).unwrap_or(0.0)
+}
+
+fn multigrid_field_or_0(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    field_configuration.multigrid_field(x, y).unwrap_or(Pair { u: 0.0, v: 0.0 })
+}
+
+fn vector_potential_or_0(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    field_configuration.vector_potential(x, y).unwrap_or(0.0)
+}
+
+/// The gradient of the vector potential, which is the in-plane field turned a quarter turn
+fn vector_potential_gradient_or_0(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    let Pair { u, v } = field_configuration.vector_potential_field(x, y).unwrap_or(Pair { u: 0.0, v: 0.0 });
+    Pair { u: -v, v: u }
+}
+
+/// Contours returned to JS, with any warnings raised while finding them
+#[derive(Serialize)]
+struct ContourResult {
+    contours: Vec<Vec<(f64, f64)>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arrows: Option<Vec<(f64, f64)>>,
+    warnings: Warnings,
 }
 
 /// Generate a contour of the potential field starting at the specified point
@@ -116,8 +191,9 @@ fn find_crossing_point(description: &ContouringCollection, level: f64, x0: f64,
         let (u_normed, v_normed) = (u/r.powi(2), v/r.powi(2));
 
         if r<1e-6 || step>MAXIMUM_NUM_STEPS {
-            console::log_1(&format!("Searching for crossing point from ({}, {}) at level {}; starting {}", x0, y0, level, orig_potential).into());
-            console::log_1(&format!("Aborted after {} steps @ {} {} : {}", step, x, y, potential).into());
+            logging::debug(format_args!("Searching for crossing point from ({}, {}) at level {}; starting {}; reached ({}, {}) at {}",
+                x0, y0, level, orig_potential, x, y, potential));
+            description.warnings.borrow_mut().push(Warning::CrossingPointNotFound { x: x0, y: y0, level, steps: step as usize });
 
             return (x0, y0);
         }
@@ -151,7 +227,7 @@ fn find_crossing_point(description: &ContouringCollection, level: f64, x0: f64,
         step += 1;
         if step > MAXIMUM_NUM_STEPS {
             let final_pot = pot_calc(field_configuration, x, y);
-            console::log_1(&format!("Aborted bisection ({} {}): {} point after {} steps", x, y, final_pot, step).into());
+            description.warnings.borrow_mut().push(Warning::BisectionNotConverged { x, y, level, potential: final_pot, steps: step as usize });
             break;
         }
     }
@@ -161,15 +237,39 @@ fn find_crossing_point(description: &ContouringCollection, level: f64, x0: f64,
     (x,y)
 }
 
+/// Marks a cell in which no contour is still to be found
+const NO_CHANGE: f64 = f64::INFINITY;
+
+/// How close a conductor's potential must be to a level for its outline to be taken as the contour
+const LEVEL_TOLERANCE: f64 = 1e-4;
+
+/// Unflag the cells at this level that have been visited by a contour, so that it is not found again from them
+fn unflag_visited_cells(crossing_level: &mut Array2<f64>, contour: &[(f64, f64)], level: f64, field_configuration: &FieldConfiguration) {
+    contour.iter().for_each(|(x, y)| {
+        field_configuration.geometry.position_to_surrounding_cells(*x, *y).iter().for_each(
+            |(it, jt)| { 
+                if crossing_level[[*it,*jt]]==level { 
+                    crossing_level[[*it,*jt]] = NO_CHANGE; 
+                }
+            }
+        );
+    });
+}
+
 fn generate_contours_at_levels(description: &ContouringCollection, levels: Vec<f64>) -> Vec<Vec<(f64, f64)>> {
     const MAX_CONTOURS: usize = 50;
-    const NO_CHANGE: f64 = f64::INFINITY;
 
     let field_configuration = description.configuration;
 
     
     // First, scan over the grid to find all cells that cross the level
     let mut crossing_level = Array2::<f64>::from_shape_fn((field_configuration.geometry.nx, field_configuration.geometry.ny), |(i, j)| {
+        // the potential is constant inside a conductor, leaving nothing to follow
+        let (x, y) = field_configuration.geometry.cell_to_centroid(i, j);
+        if description.in_conductor(x, y) {
+            return NO_CHANGE;
+        }
+
         let potential_in_corners = field_configuration.geometry.cell_to_corners(i, j).
             iter().
             map(|(x, y)| (description.potential_calculator)(field_configuration, *x, *y)).
@@ -190,9 +290,19 @@ fn generate_contours_at_levels(description: &ContouringCollection, levels: Vec<f
 
     let mut contours : Vec<Vec<(f64, f64)>> = vec![];
 
+    // A conductor's outline is the contour at its potential, which the surrounding cells would only approximate
+    let spacing = 0.5 * field_configuration.geometry.delta_x().min(field_configuration.geometry.delta_y());
+    for (region, potential) in &description.conductors {
+        if let Some(&level) = levels.iter().find(|&&level| (level - potential).abs() < LEVEL_TOLERANCE) {
+            let outline = region.outline(spacing);
+            unflag_visited_cells(&mut crossing_level, &outline, level, field_configuration);
+            contours.push(outline);
+        }
+    }
+
     loop {
         if contours.len() >= MAX_CONTOURS {
-            console::log_1(&format!("Ran out of contours").into());
+            description.warnings.borrow_mut().push(Warning::TooManyContours { limit: MAX_CONTOURS });
             break;
         }
 
@@ -214,30 +324,17 @@ fn generate_contours_at_levels(description: &ContouringCollection, levels: Vec<f
         // Now follow the contour from this cell
         let (mut x, mut y) = field_configuration.geometry.cell_to_centroid(i, j);
         
-        let distance_to_closest = field_configuration.distance_to_closest_charge(x,y);
-
-        
-        // console::log_1(&format!("Distance to closest charge: {}", distance_to_closest).into());
-        if distance_to_closest < 15. {
+        if field_configuration.in_contour_exclusion_zone(x, y) {
             continue;
         }
         
-        (x, y) = find_crossing_point(&description, level, x, y);
+        (x, y) = find_crossing_point(description, level, x, y);
 
         
         
-        let contour = generate_potential_contours(&description, x, y, level, false);
-
-        // unflag cells that have been visited by this contour
-        contour.iter().for_each(|(x, y)| {
-            field_configuration.geometry.position_to_surrounding_cells(*x, *y).iter().for_each(
-                |(it, jt)| { 
-                    if crossing_level[[*it,*jt]]==level { 
-                        crossing_level[[*it,*jt]] = NO_CHANGE; 
-                    }
-                }
-            );
-        });
+        let contour = generate_potential_contours(description, x, y, level, false);
+
+        unflag_visited_cells(&mut crossing_level, &contour, level, field_configuration);
 
 
         contours.push(contour);
@@ -249,16 +346,40 @@ fn generate_contours_at_levels(description: &ContouringCollection, levels: Vec<f
 }
 
 
-/// Generate a contour at a specified level of the electrostatic potential field
+/// Generate a contour at a specified level of the electrostatic potential field, returned as
+/// `{contours, warnings}` where warnings lists any problems met on the way. Configurations built for the
+/// ElectrostaticFourier solver are contoured on its spectral solution, and those for the ElectrostaticMultigrid solver
+/// on its multigrid solution, where a level at a conductor's potential gives that conductor's outline.
 #[wasm_bindgen]
 pub fn generate_potential_contours_at_levels(field_configuration: &FieldConfiguration, levels: Vec<f64>) -> JsValue {
-    let description  = ContouringCollection { potential_calculator: crate::compute_potential_electrostatic_direct,
-        potential_gradient_calculator: crate::compute_field_electrostatic_direct,
-        configuration: field_configuration};
+    let description = ContouringCollection::electrostatic(field_configuration);
+
+    let contours = generate_contours_at_levels(&description, levels);
+
+    to_value(&ContourResult { contours, arrows: None, warnings: description.warnings.into_inner() }).unwrap()
+}
+
+/// Generate contours at specified levels of the magnetic scalar potential of any permanent magnets, returned as
+/// `{contours, warnings}`
+#[wasm_bindgen]
+pub fn generate_magnetic_scalar_potential_contours_at_levels(field_configuration: &FieldConfiguration, levels: Vec<f64>) -> JsValue {
+    let description = ContouringCollection::new(crate::compute_potential_magnetostatic_scalar_direct,
+        crate::compute_field_magnetic_h_direct, field_configuration);
+
+    let contours = generate_contours_at_levels(&description, levels);
+
+    to_value(&ContourResult { contours, arrows: None, warnings: description.warnings.into_inner() }).unwrap()
+}
+
+/// Generate contours at specified levels of the vector potential $A_z$ from the multigrid magnetostatic solver, which
+/// are the magnetic field lines, returned as `{contours, warnings}`
+#[wasm_bindgen]
+pub fn generate_vector_potential_contours_at_levels(field_configuration: &FieldConfiguration, levels: Vec<f64>) -> JsValue {
+    let description = ContouringCollection::new(vector_potential_or_0, vector_potential_gradient_or_0, field_configuration);
 
     let contours = generate_contours_at_levels(&description, levels);
 
-    to_value(&contours).unwrap()
+    to_value(&ContourResult { contours, arrows: None, warnings: description.warnings.into_inner() }).unwrap()
 }
 
 fn line_crosses_symmetry(field_configuration: &FieldConfiguration, x0: f64, y0: f64, x1: f64, y1: f64) -> Option<Pair> {
@@ -277,7 +398,7 @@ fn line_crosses_symmetry(field_configuration: &FieldConfiguration, x0: f64, y0:
         }
     };
 
-    if field_configuration.charges.len() ==0 {
+    if field_configuration.charges.is_empty() {
         let xcrit = field_configuration.geometry.x_max/2.0;
         let ycrit = field_configuration.geometry.y_max/2.0;
         // if the line crosses either x=xcrit or y=ycrit, then return the crossing point
@@ -297,12 +418,12 @@ fn line_crosses_symmetry(field_configuration: &FieldConfiguration, x0: f64, y0:
     if field_configuration.charges.len() == 1 {
         // pretend there is another charge displaced to the right of the one charge
         let charge = &field_configuration.charges[0];
-        let other_charge = crate::Charge {x: charge.x + 1.0, y: charge.y, charge: charge.charge};
+        let other_charge = crate::Charge {x: charge.x + 1.0, ..charge.clone()};
         return crosses_symmetry(charge, &other_charge);
     }
 
     let score_pair = |charge: &crate::Charge, other_charge: &crate::Charge| -> f64 {
-        let r_squared = ((charge.x - other_charge.x).powi(2) + (charge.y - other_charge.y).powi(2));
+        let r_squared = (charge.x - other_charge.x).powi(2) + (charge.y - other_charge.y).powi(2);
         (1.3-charge.charge*other_charge.charge)/r_squared
     };
 
@@ -319,8 +440,7 @@ fn line_crosses_symmetry(field_configuration: &FieldConfiguration, x0: f64, y0:
         let mut best_score = 0.0;
         let mut best_other_charge = None;
         let mut best_other_charge_index = 0;
-        for j in 0..field_configuration.charges.len() {
-            let other_charge = &field_configuration.charges[j];
+        for (j, other_charge) in field_configuration.charges.iter().enumerate() {
             if charge == other_charge || charge_already_paired[j] {
                 continue;
             }
@@ -339,9 +459,8 @@ fn line_crosses_symmetry(field_configuration: &FieldConfiguration, x0: f64, y0:
         charge_already_paired[i] = true;
         charge_already_paired[best_other_charge_index] = true;
         
-        match crosses_symmetry(charge, best_other_charge.unwrap()) {
-            Some(pair) => return Some(pair),
-            None => (),
+        if let Some(pair) = crosses_symmetry(charge, best_other_charge.unwrap()) {
+            return Some(pair);
         }
             
         
@@ -350,13 +469,13 @@ fn line_crosses_symmetry(field_configuration: &FieldConfiguration, x0: f64, y0:
     None
 }
 
+/// As generate_potential_contours_at_levels, returning `{contours, arrows, warnings}` with the positions of
+/// direction arrows along the contours
 #[wasm_bindgen]
 pub fn generate_potential_contours_and_arrow_positions_at_levels(field_configuration: &FieldConfiguration, levels: Vec<f64>) -> JsValue {
-    let description  = ContouringCollection { potential_calculator: crate::compute_potential_electrostatic_direct,
-        potential_gradient_calculator: crate::compute_field_electrostatic_direct,
-        configuration: field_configuration};
+    let description = ContouringCollection::electrostatic(field_configuration);
 
-    let IDEAL_ARROW_SPACING: usize = 60;
+    const IDEAL_ARROW_SPACING: usize = 60;
 
     let contours = generate_contours_at_levels(&description, levels);
     let mut arrows: Vec<(f64, f64)> = vec![];
@@ -377,13 +496,10 @@ pub fn generate_potential_contours_and_arrow_positions_at_levels(field_configura
                 steps_until_another_arrow_allowed -= 1;
                 continue;
             }
-            match line_crosses_symmetry(field_configuration, *x0, *y0, *x1, *y1) {
-                Some(Pair {u, v}) => { 
-                    steps_until_another_arrow_allowed = IDEAL_ARROW_SPACING/4;
-                    arrows.push((u, v));
-                    arrows_at_step.push(this_step);
-                },
-                None => (),
+            if let Some(Pair {u, v}) = line_crosses_symmetry(field_configuration, *x0, *y0, *x1, *y1) {
+                steps_until_another_arrow_allowed = IDEAL_ARROW_SPACING/4;
+                arrows.push((u, v));
+                arrows_at_step.push(this_step);
             }
             this_step += 1;
         }
@@ -391,7 +507,7 @@ pub fn generate_potential_contours_and_arrow_positions_at_levels(field_configura
         if !contour_is_closed {
             arrows_at_step.push(contour.len()-1);
         } else {
-            if arrows_at_step.len() > 0 {
+            if !arrows_at_step.is_empty() {
                 arrows_at_step.push(arrows_at_step[0]+contour.len());
             }
         }
@@ -417,5 +533,5 @@ pub fn generate_potential_contours_and_arrow_positions_at_levels(field_configura
 
     }
 
-    to_value(&(contours, arrows)).unwrap()
+    to_value(&ContourResult { contours, arrows: Some(arrows), warnings: description.warnings.into_inner() }).unwrap()
 }
@@ -0,0 +1,94 @@
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+use crate::Pair;
+use crate::softening::ForceLaw;
+
+/// A point electric dipole at (x, y) with the given moment, pointing at angle (radians, anticlockwise from +x)
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Dipole {
+    pub x: f64,
+    pub y: f64,
+    pub moment: f64,
+    pub angle: f64,
+}
+
+#[wasm_bindgen]
+impl Dipole {
+    #[wasm_bindgen(constructor)]
+    pub fn new(x: f64, y: f64, moment: f64, angle: f64) -> Dipole {
+        Dipole { x, y, moment, angle }
+    }
+}
+
+impl Dipole {
+    /// Cartesian components of the dipole moment
+    pub fn moment_vector(&self) -> (f64, f64) {
+        (self.moment * self.angle.cos(), self.moment * self.angle.sin())
+    }
+
+    /// Potential factor at (x, y), without the overall field scaling; the potential is minus this
+    pub fn potential(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> f64 {
+        let (px, py) = self.moment_vector();
+        dipole_potential(x - self.x, y - self.y, px, py, epsilon, law)
+    }
+
+    /// Field at (x, y), without the overall field scaling
+    pub fn field(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> Pair {
+        let (px, py) = self.moment_vector();
+        dipole_field(x - self.x, y - self.y, px, py, epsilon, law)
+    }
+
+    /// Force $\nabla(\vec{p}\cdot\vec{E})$ (by central differences) and torque $\vec{p}\times\vec{E}$ from an
+    /// external field
+    pub fn force_and_torque(&self, field_external: impl Fn(f64, f64) -> Pair, epsilon: f64) -> (Pair, f64) {
+        let (px, py) = self.moment_vector();
+        dipole_force_and_torque(self.x, self.y, px, py, field_external, epsilon)
+    }
+}
+
+/// $\vec{p}\cdot\vec{r} / s^3$ for the inverse-square law, or $\vec{p}\cdot\vec{r} / s^2$ for the logarithmic law,
+/// with $s^2 = r^2+\epsilon^2$, for a dipole displaced by (dx, dy) from the point of interest
+pub fn dipole_potential(dx: f64, dy: f64, px: f64, py: f64, epsilon: f64, law: ForceLaw) -> f64 {
+    let s2 = dx * dx + dy * dy + epsilon * epsilon;
+    if s2 == 0.0 {
+        return 0.0;
+    }
+    match law {
+        ForceLaw::InverseSquare => (px * dx + py * dy) / (s2 * s2.sqrt()),
+        ForceLaw::Logarithmic => (px * dx + py * dy) / s2,
+    }
+}
+
+/// The (Plummer-softened) dipole field, $3(\vec{p}\cdot\vec{r})\vec{r}/s^5 - \vec{p}/s^3$ for the inverse-square law
+/// or $2(\vec{p}\cdot\vec{r})\vec{r}/s^4 - \vec{p}/s^2$ for the logarithmic law
+pub fn dipole_field(dx: f64, dy: f64, px: f64, py: f64, epsilon: f64, law: ForceLaw) -> Pair {
+    let s2 = dx * dx + dy * dy + epsilon * epsilon;
+    if s2 == 0.0 {
+        return Pair { u: 0.0, v: 0.0 };
+    }
+    let p_dot_r = px * dx + py * dy;
+    let (radial, transverse) = match law {
+        ForceLaw::InverseSquare => {
+            let s3 = s2 * s2.sqrt();
+            (3.0 * p_dot_r / (s3 * s2), 1.0 / s3)
+        },
+        ForceLaw::Logarithmic => (2.0 * p_dot_r / (s2 * s2), 1.0 / s2),
+    };
+    Pair { u: radial * dx - px * transverse, v: radial * dy - py * transverse }
+}
+
+/// Force and torque on a point dipole (px, py) at (x, y) in a curl-free external field
+pub fn dipole_force_and_torque(x: f64, y: f64, px: f64, py: f64, field_external: impl Fn(f64, f64) -> Pair, epsilon: f64) -> (Pair, f64) {
+    let step = 1e-3 * epsilon.max(1.0);
+    let p_dot_e = |x: f64, y: f64| {
+        let Pair { u, v } = field_external(x, y);
+        px * u + py * v
+    };
+    let force = Pair {
+        u: (p_dot_e(x + step, y) - p_dot_e(x - step, y)) / (2.0 * step),
+        v: (p_dot_e(x, y + step) - p_dot_e(x, y - step)) / (2.0 * step),
+    };
+    let Pair { u: ex, v: ey } = field_external(x, y);
+    (force, px * ey - py * ex)
+}
@@ -0,0 +1,198 @@
+use wasm_bindgen::prelude::*;
+use crate::{Pair, FieldConfiguration, compute_forces_electrostatic};
+use crate::error::MaxwellError;
+
+/// A group of charges moving together as a rigid body, such as the two charges making up a dipole
+struct RigidBody {
+    /// Indices of the member charges in the field configuration
+    members: Vec<usize>,
+    /// Positions of the members relative to the centre of mass, when angle is zero
+    offsets: Vec<(f64, f64)>,
+    total_mass: f64,
+    moment_of_inertia: f64,
+    com: (f64, f64),
+    velocity: (f64, f64),
+    angle: f64,
+    angular_velocity: f64,
+}
+
+impl RigidBody {
+    fn new(field_config: &FieldConfiguration, members: Vec<usize>, masses: Vec<f64>) -> Result<RigidBody, MaxwellError> {
+        if masses.len() != members.len() {
+            return Err(MaxwellError::MassCountMismatch { charges: members.len(), masses: masses.len() });
+        }
+        let count = field_config.charges.len();
+        if let Some(&index) = members.iter().find(|&&i| i >= count) {
+            return Err(MaxwellError::NoSuchCharge { index, count });
+        }
+        if let Some(&mass) = masses.iter().find(|m| !(m.is_finite() && **m > 0.0)) {
+            return Err(MaxwellError::InvalidMass { mass });
+        }
+        let total_mass: f64 = masses.iter().sum();
+        if total_mass == 0.0 {
+            // only possible for a group with no charges
+            return Err(MaxwellError::InvalidMass { mass: total_mass });
+        }
+        let (mut cx, mut cy) = (0.0, 0.0);
+        for (&i, &m) in members.iter().zip(masses.iter()) {
+            cx += m * field_config.charges[i].x;
+            cy += m * field_config.charges[i].y;
+        }
+        let com = (cx / total_mass, cy / total_mass);
+        let offsets: Vec<(f64, f64)> = members.iter().map(|&i| (field_config.charges[i].x - com.0, field_config.charges[i].y - com.1)).collect();
+        let moment_of_inertia = offsets.iter().zip(masses.iter()).map(|((dx, dy), m)| m * (dx * dx + dy * dy)).sum();
+        Ok(RigidBody { members, offsets, total_mass, moment_of_inertia, com, velocity: (0.0, 0.0), angle: 0.0, angular_velocity: 0.0 })
+    }
+
+    /// Whether all the member charges are still in the field configuration
+    fn is_present(&self, field_config: &FieldConfiguration) -> bool {
+        self.members.iter().all(|&i| i < field_config.charges.len())
+    }
+
+    /// Net force and torque about the centre of mass, from the forces on every charge, all of whose members must be
+    /// present
+    fn net_force_and_torque(&self, field_config: &FieldConfiguration, forces: &[Pair]) -> (Pair, f64) {
+        let mut net = Pair { u: 0.0, v: 0.0 };
+        let mut torque = 0.0;
+        for &i in &self.members {
+            let Pair { u, v } = forces[i];
+            net.u += u;
+            net.v += v;
+            torque += (field_config.charges[i].x - self.com.0) * v - (field_config.charges[i].y - self.com.1) * u;
+        }
+        (net, torque)
+    }
+
+    fn kick(&mut self, force: &Pair, torque: f64, dt: f64) {
+        self.velocity.0 += force.u / self.total_mass * dt;
+        self.velocity.1 += force.v / self.total_mass * dt;
+        if self.moment_of_inertia > 0.0 {
+            self.angular_velocity += torque / self.moment_of_inertia * dt;
+        }
+    }
+
+    fn drift(&mut self, dt: f64) {
+        self.com.0 += self.velocity.0 * dt;
+        self.com.1 += self.velocity.1 * dt;
+        self.angle += self.angular_velocity * dt;
+    }
+
+    /// Write the member positions implied by the centre of mass and angle back into the field configuration, which
+    /// must contain all the members
+    fn place_charges(&self, field_config: &mut FieldConfiguration) {
+        let (sin, cos) = self.angle.sin_cos();
+        for (&i, (dx, dy)) in self.members.iter().zip(self.offsets.iter()) {
+            field_config.charges[i].x = self.com.0 + cos * dx - sin * dy;
+            field_config.charges[i].y = self.com.1 + sin * dx + cos * dy;
+        }
+        field_config.charges_changed();
+    }
+}
+
+/// Rigid-body dynamics for groups of linked charges, driven by the direct electrostatic forces.
+///
+/// Each step is a velocity Verlet (kick-drift-kick) update of the centre of mass and orientation of every group.
+/// Charges that do not belong to any group stay where they are, as do groups some of whose charges have since been
+/// removed.
+#[wasm_bindgen]
+pub struct RigidBodyIntegrator {
+    bodies: Vec<RigidBody>,
+    /// Fractional loss of linear and angular velocity per unit time
+    damping: f64,
+}
+
+impl Default for RigidBodyIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl RigidBodyIntegrator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> RigidBodyIntegrator {
+        RigidBodyIntegrator { bodies: vec![], damping: 0.0 }
+    }
+
+    /// Link the charges with the given indices into a rigid body, returning the index of the new body, or throwing if
+    /// a charge does not exist or the masses are not all positive.
+    ///
+    /// If masses is empty, every charge is given unit mass.
+    pub fn add_group(&mut self, field_config: &FieldConfiguration, members: Vec<usize>, masses: Vec<f64>) -> Result<usize, JsError> {
+        Ok(self.try_add_group(field_config, members, masses)?)
+    }
+
+    pub fn clear(&mut self) {
+        self.bodies.clear();
+    }
+
+    pub fn set_damping(&mut self, damping: f64) {
+        self.damping = damping;
+    }
+
+    /// The angle of a body, throwing if there is no such body, as for the other getters below
+    pub fn angle(&self, body: usize) -> Result<f64, JsError> {
+        Ok(self.body(body)?.angle)
+    }
+
+    pub fn angular_velocity(&self, body: usize) -> Result<f64, JsError> {
+        Ok(self.body(body)?.angular_velocity)
+    }
+
+    pub fn centre_of_mass(&self, body: usize) -> Result<Pair, JsError> {
+        let (u, v) = self.body(body)?.com;
+        Ok(Pair { u, v })
+    }
+
+    pub fn velocity(&self, body: usize) -> Result<Pair, JsError> {
+        let (u, v) = self.body(body)?.velocity;
+        Ok(Pair { u, v })
+    }
+
+    /// Total kinetic energy (translational plus rotational) of all bodies
+    pub fn kinetic_energy(&self) -> f64 {
+        self.bodies.iter().map(|b| {
+            0.5 * b.total_mass * (b.velocity.0.powi(2) + b.velocity.1.powi(2)) + 0.5 * b.moment_of_inertia * b.angular_velocity.powi(2)
+        }).sum()
+    }
+
+    /// Advance all bodies by dt, moving their charges within field_config
+    pub fn step(&mut self, field_config: &mut FieldConfiguration, dt: f64) {
+        let forces = compute_forces_electrostatic(field_config);
+        for body in &mut self.bodies {
+            if !body.is_present(field_config) {
+                continue;
+            }
+            let (force, torque) = body.net_force_and_torque(field_config, &forces);
+            body.kick(&force, torque, 0.5 * dt);
+            body.drift(dt);
+            body.place_charges(field_config);
+        }
+
+        let forces = compute_forces_electrostatic(field_config);
+        let damping_factor = (-self.damping * dt).exp();
+        for body in &mut self.bodies {
+            if !body.is_present(field_config) {
+                continue;
+            }
+            let (force, torque) = body.net_force_and_torque(field_config, &forces);
+            body.kick(&force, torque, 0.5 * dt);
+            body.velocity.0 *= damping_factor;
+            body.velocity.1 *= damping_factor;
+            body.angular_velocity *= damping_factor;
+        }
+    }
+}
+
+impl RigidBodyIntegrator {
+    /// Link the charges with the given indices into a rigid body, as for add_group
+    pub fn try_add_group(&mut self, field_config: &FieldConfiguration, members: Vec<usize>, masses: Vec<f64>) -> Result<usize, MaxwellError> {
+        let masses = if masses.is_empty() { vec![1.0; members.len()] } else { masses };
+        self.bodies.push(RigidBody::new(field_config, members, masses)?);
+        Ok(self.bodies.len() - 1)
+    }
+
+    fn body(&self, index: usize) -> Result<&RigidBody, MaxwellError> {
+        self.bodies.get(index).ok_or(MaxwellError::NoSuchBody { index, count: self.bodies.len() })
+    }
+}
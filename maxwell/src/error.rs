@@ -0,0 +1,107 @@
+use std::fmt;
+use serde::de::DeserializeOwned;
+use serde_wasm_bindgen::from_value;
+use wasm_bindgen::prelude::*;
+use crate::builder::{ConfigurationError, SolverKind};
+use crate::solver::SolverCapability;
+use crate::stencil::StencilType;
+
+/// A reason that a FieldConfiguration could not carry out a request
+///
+/// Methods exposed to JS throw these as exceptions (via JsError), so that an embed can catch them and carry on.
+/// Native callers receive them directly, from the `try_` variants where the JS method has the same name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaxwellError {
+    /// A value passed from JS could not be read as the expected type
+    Deserialization { what: &'static str, message: String },
+    /// A charge lies outside the grid, so it cannot be deposited or moved on it
+    ChargeOutOfBounds { x: f64, y: f64 },
+    /// A point lies outside the physical region covered by the grid
+    PointOutOfBounds { x: f64, y: f64 },
+    /// Charges were added or removed since the last tick, without the dynamic fields being reinitialised
+    ChargeCountChanged { before: usize, after: usize },
+    /// A charge's strength changed since the last tick, without the dynamic fields being reinitialised
+    ChargeStrengthChanged { index: usize, before: f64, after: f64 },
+    /// The stencil can only be applied in Fourier space, not evaluated at a single cell
+    UnsupportedStencil { stencil: StencilType },
+    /// The configuration's options are inconsistent
+    Configuration(ConfigurationError),
+    /// The solver cannot compute the requested quantity
+    Unsupported { solver: SolverKind, capability: SolverCapability },
+    /// Values given cell by cell do not match the number of cells in the grid
+    GridSizeMismatch { what: &'static str, expected: usize, found: usize },
+    /// A charge was referred to by an index beyond the end of the charges
+    NoSuchCharge { index: usize, count: usize },
+    /// A rigid body was referred to by an index beyond the end of the bodies
+    NoSuchBody { index: usize, count: usize },
+    /// A rigid body was given a different number of masses from its charges
+    MassCountMismatch { charges: usize, masses: usize },
+    /// Masses must be positive and finite, and a rigid body needs at least one charge
+    InvalidMass { mass: f64 },
+}
+
+impl fmt::Display for MaxwellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaxwellError::Deserialization { what, message } => {
+                write!(f, "error deserializing {}: {}", what, message)
+            },
+            MaxwellError::ChargeOutOfBounds { x, y } => {
+                write!(f, "charge at ({}, {}) is outside the grid", x, y)
+            },
+            MaxwellError::PointOutOfBounds { x, y } => {
+                write!(f, "point ({}, {}) is outside the grid", x, y)
+            },
+            MaxwellError::ChargeCountChanged { before, after } => {
+                write!(f, "number of charges changed from {} to {} since the last tick", before, after)
+            },
+            MaxwellError::ChargeStrengthChanged { index, before, after } => {
+                write!(f, "strength of charge {} changed from {} to {} since the last tick", index, before, after)
+            },
+            MaxwellError::UnsupportedStencil { stencil } => {
+                write!(f, "stencil {:?} cannot be evaluated in real space", stencil)
+            },
+            MaxwellError::Configuration(err) => {
+                write!(f, "invalid configuration: {}", err)
+            },
+            MaxwellError::Unsupported { solver, capability } => {
+                write!(f, "the {:?} solver does not support {:?}", solver, capability)
+            },
+            MaxwellError::GridSizeMismatch { what, expected, found } => {
+                write!(f, "{} has {} values, but the grid has {} cells", what, found, expected)
+            },
+            MaxwellError::NoSuchCharge { index, count } => {
+                write!(f, "there is no charge {}, as there are only {} charges", index, count)
+            },
+            MaxwellError::NoSuchBody { index, count } => {
+                write!(f, "there is no rigid body {}, as there are only {} bodies", index, count)
+            },
+            MaxwellError::MassCountMismatch { charges, masses } => {
+                write!(f, "{} masses were given for {} charges", masses, charges)
+            },
+            MaxwellError::InvalidMass { mass } => {
+                write!(f, "mass {} is not positive and finite", mass)
+            },
+        }
+    }
+}
+
+impl std::error::Error for MaxwellError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MaxwellError::Configuration(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigurationError> for MaxwellError {
+    fn from(err: ConfigurationError) -> Self {
+        MaxwellError::Configuration(err)
+    }
+}
+
+/// Read a value passed from JS, describing it as `what` if it has the wrong form
+pub(crate) fn deserialize<T: DeserializeOwned>(value: JsValue, what: &'static str) -> Result<T, MaxwellError> {
+    from_value(value).map_err(|err| MaxwellError::Deserialization { what, message: err.to_string() })
+}
@@ -0,0 +1,121 @@
+use crate::Pair;
+use crate::softening::{SofteningKernel, ForceLaw, erf};
+
+/// Both sums are truncated where the Gaussian damping reaches exp(-16), so that neglected terms are ~1e-8
+const EWALD_TOLERANCE_ARGUMENT: f64 = 4.0;
+
+/// Ewald summation of the direct solvers' interaction over the periodic images of a rectangular cell.
+///
+/// The cell is periodic in x and y only, so this is the sum over a two-dimensional lattice of images lying in the
+/// plane. The interaction is split into a short-range part, summed over nearby images in real space, and the
+/// potential of a Gaussian of width $1/(\sqrt{2}\alpha)$, summed over reciprocal lattice vectors. A uniform
+/// neutralising background is implied, so that a cell with net charge still has a finite potential, whose average
+/// over the cell is zero.
+pub struct EwaldSum {
+    law: ForceLaw,
+    cell: (f64, f64),
+    /// The Gaussian whose potential is handled in reciprocal space
+    smooth_kernel: SofteningKernel,
+    /// Offsets of the images summed in real space
+    images: Vec<(f64, f64)>,
+    /// Reciprocal lattice vectors in one half-plane, as (kx, ky, coefficient)
+    wavevectors: Vec<(f64, f64, f64)>,
+    /// The k = 0 term, a constant offset to the potential from the neutralising background
+    constant: f64,
+}
+
+impl EwaldSum {
+    pub fn new(x_max: f64, y_max: f64, law: ForceLaw) -> EwaldSum {
+        let area = x_max * y_max;
+        // balance the work between the two sums
+        let alpha = std::f64::consts::PI.sqrt() / area.sqrt();
+
+        let r_cut = EWALD_TOLERANCE_ARGUMENT / alpha;
+        let nx = (r_cut / x_max).ceil() as i64;
+        let ny = (r_cut / y_max).ceil() as i64;
+        let mut images = vec![];
+        for i in -nx..=nx {
+            for j in -ny..=ny {
+                images.push((i as f64 * x_max, j as f64 * y_max));
+            }
+        }
+
+        let k_cut = 2.0 * EWALD_TOLERANCE_ARGUMENT * alpha;
+        let mx = (k_cut * x_max / (2.0 * std::f64::consts::PI)).ceil() as i64;
+        let my = (k_cut * y_max / (2.0 * std::f64::consts::PI)).ceil() as i64;
+        let mut wavevectors = vec![];
+        for i in 0..=mx {
+            for j in -my..=my {
+                // -k contributes the same as k, so only one half-plane is stored, with a doubled coefficient
+                if i == 0 && j <= 0 {
+                    continue;
+                }
+                let kx = 2.0 * std::f64::consts::PI * i as f64 / x_max;
+                let ky = 2.0 * std::f64::consts::PI * j as f64 / y_max;
+                let k = (kx * kx + ky * ky).sqrt();
+                // the two-dimensional transform of the smooth potential, per unit cell area
+                let transform = match law {
+                    ForceLaw::InverseSquare => (1.0 - erf(k / (2.0 * alpha))) / k,
+                    ForceLaw::Logarithmic => (-k * k / (4.0 * alpha * alpha)).exp() / (k * k),
+                };
+                wavevectors.push((kx, ky, 2.0 * 2.0 * std::f64::consts::PI / area * transform));
+            }
+        }
+
+        // subtract the cell average of the real-space sum, which is the integral of the short-range part
+        let constant = match law {
+            ForceLaw::InverseSquare => -2.0 * std::f64::consts::PI.sqrt() / (area * alpha),
+            ForceLaw::Logarithmic => -std::f64::consts::PI / (2.0 * area * alpha * alpha),
+        };
+
+        let smooth_kernel = SofteningKernel::Gaussian { width: 1.0 / (std::f64::consts::SQRT_2 * alpha) };
+
+        EwaldSum { law, cell: (x_max, y_max), smooth_kernel, images, wavevectors, constant }
+    }
+
+    /// Whether the sum is for a cell of x_max by y_max with this force law
+    pub fn is_for(&self, x_max: f64, y_max: f64, law: ForceLaw) -> bool {
+        self.cell == (x_max, y_max) && self.law == law
+    }
+
+    /// Wrap a separation into the cell centred on the origin
+    fn minimum_image(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let (lx, ly) = self.cell;
+        (dx - lx * (dx / lx).round(), dy - ly * (dy / ly).round())
+    }
+
+    /// The potential factor of a unit charge and all its images at separation (dx, dy), with the same sign
+    /// convention as SofteningKernel::potential_factor and without the overall field scaling.
+    pub fn potential(&self, dx: f64, dy: f64, kernel: &SofteningKernel) -> f64 {
+        let (dx, dy) = self.minimum_image(dx, dy);
+        let mut potential = self.constant;
+        for (ox, oy) in &self.images {
+            let r2 = (dx + ox).powi(2) + (dy + oy).powi(2);
+            potential += kernel.potential_factor(self.law, r2) - self.smooth_kernel.potential_factor(self.law, r2);
+        }
+        for (kx, ky, coefficient) in &self.wavevectors {
+            potential += coefficient * (kx * dx + ky * dy).cos();
+        }
+        potential
+    }
+
+    /// The field of a unit charge and all its images at separation (dx, dy), without the overall field scaling
+    pub fn field(&self, dx: f64, dy: f64, kernel: &SofteningKernel) -> Pair {
+        let (dx, dy) = self.minimum_image(dx, dy);
+        let mut u = 0.0;
+        let mut v = 0.0;
+        for (ox, oy) in &self.images {
+            let (rx, ry) = (dx + ox, dy + oy);
+            let r2 = rx * rx + ry * ry;
+            let k = kernel.field_factor(self.law, r2) - self.smooth_kernel.field_factor(self.law, r2);
+            u += k * rx;
+            v += k * ry;
+        }
+        for (kx, ky, coefficient) in &self.wavevectors {
+            let s = coefficient * (kx * dx + ky * dy).sin();
+            u += s * kx;
+            v += s * ky;
+        }
+        Pair { u, v }
+    }
+}
@@ -1,20 +1,25 @@
-use ndarray;
 use fft2d::slice::{fft_2d, ifft_2d};
 use num_complex::Complex;
 
 
+// The arrays are stored in row-major order, so each row of the image seen by fft2d runs along the second axis. The
+// transform is left transposed, which is harmless since stencils are transformed in the same way, and the inverse
+// transform of the transposed buffer restores the original layout. fft2d takes the width before the height, so
+// array_fft passes the second axis first and array_ifft, which sees the transposed buffer, the first axis first. With
+// the axes the other way round a non-square grid is transformed along the wrong strides.
+
 pub fn array_fft(array: &mut ndarray::Array2<Complex<f64>> ) {
-    let width = array.shape()[0];
-    let height = array.shape()[1];
-    let mut slice = array.as_slice_mut().unwrap();
-    fft_2d( width, height, &mut slice);
+    let width = array.shape()[1];
+    let height = array.shape()[0];
+    let slice = array.as_slice_mut().unwrap();
+    fft_2d( width, height, slice);
 }
 
 pub fn array_ifft(array: &mut ndarray::Array2<Complex<f64>> ) {
     let width = array.shape()[0];
     let height = array.shape()[1];
-    let mut slice = array.as_slice_mut().unwrap();
-    ifft_2d( width, height, &mut slice);
+    let slice = array.as_slice_mut().unwrap();
+    ifft_2d( width, height, slice);
 }
 
 pub fn array_fft_renormalise(array: &mut ndarray::Array2<Complex<f64>> ) {
@@ -22,5 +27,48 @@ pub fn array_fft_renormalise(array: &mut ndarray::Array2<Complex<f64>> ) {
     let height = array.shape()[1];
     let norm_factor = width*height;
     let slice = array.as_slice_mut().unwrap();
-    slice.iter_mut().for_each(|x| *x = *x / (norm_factor as f64) );
+    slice.iter_mut().for_each(|x| *x /= norm_factor as f64 );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+    use std::f64::consts::PI;
+
+    const NX: usize = 4;
+    const NY: usize = 8;
+
+    fn non_square_array() -> Array2<Complex<f64>> {
+        Array2::from_shape_fn((NX, NY), |(i, j)| Complex::new((i * NY + j) as f64 + 0.5 * i as f64, j as f64 - 3.0 * i as f64))
+    }
+
+    #[test]
+    fn forward_transform_of_non_square_array_is_transposed_dft() {
+        let array = non_square_array();
+        let mut transform = array.clone();
+        array_fft(&mut transform);
+        let transform = transform.as_slice().unwrap();
+        for kx in 0..NX {
+            for ky in 0..NY {
+                let expected: Complex<f64> = array.indexed_iter().map(|((i, j), value)| {
+                    let phase = -2.0 * PI * ((kx * i) as f64 / NX as f64 + (ky * j) as f64 / NY as f64);
+                    value * Complex::new(phase.cos(), phase.sin())
+                }).sum();
+                assert!((transform[ky * NX + kx] - expected).norm() < 1e-9, "({kx}, {ky})");
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_transform_of_non_square_array_restores_it() {
+        let array = non_square_array();
+        let mut round_trip = array.clone();
+        array_fft(&mut round_trip);
+        array_ifft(&mut round_trip);
+        array_fft_renormalise(&mut round_trip);
+        for (value, expected) in round_trip.iter().zip(array.iter()) {
+            assert!((value - expected).norm() < 1e-9);
+        }
+    }
 }
@@ -1,24 +1,73 @@
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+
+/// The number of boundary cells on each side of the physical region
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundaryWidths {
+    pub x_low: usize,
+    pub x_high: usize,
+    pub y_low: usize,
+    pub y_high: usize,
+}
+
+impl BoundaryWidths {
+    pub fn uniform(width: usize) -> BoundaryWidths {
+        BoundaryWidths { x_low: width, x_high: width, y_low: width, y_high: width }
+    }
+
+    pub fn x_total(&self) -> usize {
+        self.x_low + self.x_high
+    }
+
+    pub fn y_total(&self) -> usize {
+        self.y_low + self.y_high
+    }
+}
+
+/// What happens to fields at the edges of the grid
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryCondition {
+    /// Waves are damped in the boundary cells, to mimic an infinite domain
+    #[default]
+    Absorbing,
+    /// The grid wraps around, with no damping
+    Periodic,
+    /// Static solutions on the grid are those of open space, found by zero-padding the grid to a little over twice its
+    /// size before convolving with the Green's function. Waves are damped in the boundary cells, as for Absorbing.
+    Isolated,
+}
+
+impl BoundaryCondition {
+    /// Whether waves are damped in the boundary cells
+    pub fn is_absorbing(&self) -> bool {
+        *self != BoundaryCondition::Periodic
+    }
+}
+
 #[derive(Clone)]
 pub struct Geometry {
     /// maximum x extent of the physical region. Physical region runs from 0 to x_max; boundary cells extend further.
     pub x_max: f64, 
     /// maximum y extent of the physical region. Physical region runs from 0 to y_max; boundary cells extend further.
     pub y_max: f64, 
-    /// number of grid cells in x direction, including boundary cells (=2*nboundary)
+    /// number of grid cells in x direction, including boundary cells
     pub nx: usize, 
-    /// number of grid cells in y direction, including boundary cells (=2*nboundary)
+    /// number of grid cells in y direction, including boundary cells
     pub ny: usize, 
     /// number of boundary cells on each side
-    pub nboundary: usize, 
+    pub boundary: BoundaryWidths, 
 }
 
 impl Geometry {
     pub fn delta_x(&self) -> f64 {
-        self.x_max / (self.nx - 2 * self.nboundary) as f64
+        self.x_max / (self.nx - self.boundary.x_total()) as f64
     }
 
     pub fn delta_y(&self) -> f64 {
-        self.y_max / (self.ny - 2 * self.nboundary) as f64
+        self.y_max / (self.ny - self.boundary.y_total()) as f64
     }
 
     pub fn position_to_cell(&self, x: f64, y: f64) -> Option<(usize, usize)> {
@@ -46,39 +95,44 @@ impl Geometry {
     }
 
     pub fn position_to_cell_unclamped(&self, x: f64, y: f64) -> (isize, isize) {
-        let i = (x / self.x_max * (self.nx - 2*self.nboundary) as f64) as isize + self.nboundary as isize;
-        let j = (y / self.y_max * (self.ny - 2*self.nboundary) as f64) as isize + self.nboundary as isize;
+        let i = (x / self.x_max * (self.nx - self.boundary.x_total()) as f64).floor() as isize + self.boundary.x_low as isize;
+        let j = (y / self.y_max * (self.ny - self.boundary.y_total()) as f64).floor() as isize + self.boundary.y_low as isize;
         (i, j)
     }
 
     pub fn cell_to_centroid(&self, i: usize, j: usize) -> (f64, f64) {
-        let x = (i as f64 - self.nboundary as f64 + 0.5) * self.delta_x();
-        let y = (j as f64 - self.nboundary as f64 + 0.5) * self.delta_y();
+        self.cell_to_centroid_unclamped(i as isize, j as isize)
+    }
+
+    /// The centroid of cell (i, j), which may lie beyond the edge of the grid
+    pub fn cell_to_centroid_unclamped(&self, i: isize, j: isize) -> (f64, f64) {
+        let x = (i as f64 - self.boundary.x_low as f64 + 0.5) * self.delta_x();
+        let y = (j as f64 - self.boundary.y_low as f64 + 0.5) * self.delta_y();
         (x, y)
     }
 
     pub fn cell_to_corners(&self, i: usize, j: usize) -> Vec<(f64, f64)> {
-        let x0 = (i as f64 - self.nboundary as f64) * self.delta_x();
-        let x1 = (i as f64 - self.nboundary as f64 + 1.0) * self.delta_x();
-        let y0 = (j as f64 - self.nboundary as f64) * self.delta_y();
-        let y1 = (j as f64 - self.nboundary as f64 + 1.0) * self.delta_y();
+        let x0 = (i as f64 - self.boundary.x_low as f64) * self.delta_x();
+        let x1 = (i as f64 - self.boundary.x_low as f64 + 1.0) * self.delta_x();
+        let y0 = (j as f64 - self.boundary.y_low as f64) * self.delta_y();
+        let y1 = (j as f64 - self.boundary.y_low as f64 + 1.0) * self.delta_y();
         
         vec![(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
     }
 
     pub fn x_extent_including_boundary(&self) -> f64 {
-        self.x_max * self.nx as f64 / (self.nx - 2*self.nboundary) as f64
+        self.x_max * self.nx as f64 / (self.nx - self.boundary.x_total()) as f64
     }
 
     pub fn y_extent_including_boundary(&self) -> f64 {
-        self.y_max * self.ny as f64 / (self.ny - 2*self.nboundary) as f64
+        self.y_max * self.ny as f64 / (self.ny - self.boundary.y_total()) as f64
     }
 
     pub fn in_padding_region(&self, x: f64, y: f64) -> bool {
-        let min_x = -(self.nboundary as f64) * self.delta_x();
-        let max_x = (self.nx as f64 - self.nboundary as f64) * self.delta_x();
-        let min_y = -(self.nboundary as f64) * self.delta_y();
-        let max_y = (self.ny as f64 - self.nboundary as f64) * self.delta_y();
+        let min_x = -(self.boundary.x_low as f64) * self.delta_x();
+        let max_x = (self.nx as f64 - self.boundary.x_low as f64) * self.delta_x();
+        let min_y = -(self.boundary.y_low as f64) * self.delta_y();
+        let max_y = (self.ny as f64 - self.boundary.y_low as f64) * self.delta_y();
         x>=min_x && x<=max_x && y>=min_y && y<=max_y
     }
 
@@ -0,0 +1,77 @@
+use ndarray::Array2;
+use crate::{Pair, Charge, interpolate_grid};
+use crate::error::MaxwellError;
+use crate::geometry::Geometry;
+
+/// A potential and its field found on the grid for a set of charges, both at the cell centroids.
+///
+/// The static grid solvers keep their solution until the charges change. The field is left to each solver, which may
+/// difference the potential or find the field some other way, and may rotate it, as for a vector potential.
+pub struct GridSolution {
+    charges: Vec<Charge>, // The charges that were solved for
+    potential: Array2<f64>,
+    field_x: Array2<f64>,
+    field_y: Array2<f64>,
+}
+
+impl GridSolution {
+    pub fn new(charges: &[Charge], potential: Array2<f64>, field_x: Array2<f64>, field_y: Array2<f64>) -> GridSolution {
+        GridSolution { charges: charges.to_vec(), potential, field_x, field_y }
+    }
+
+    /// Whether the solution is for these charges
+    pub fn is_for(&self, charges: &[Charge]) -> bool {
+        self.charges == charges
+    }
+
+    /// The potential at each cell centroid
+    pub fn potential_grid(&self) -> &Array2<f64> {
+        &self.potential
+    }
+
+    /// The potential at each cell centroid, for an iterative solver to start from
+    pub fn into_potential_grid(self) -> Array2<f64> {
+        self.potential
+    }
+
+    /// The potential at (x, y), anywhere on the grid including the boundary cells
+    pub fn potential(&self, geometry: &Geometry, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        check_on_grid(geometry, x, y)?;
+        Ok(interpolate_grid(geometry, &self.potential, x, y))
+    }
+
+    /// The field at (x, y), anywhere on the grid including the boundary cells
+    pub fn field(&self, geometry: &Geometry, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        check_on_grid(geometry, x, y)?;
+        Ok(Pair { u: interpolate_grid(geometry, &self.field_x, x, y), v: interpolate_grid(geometry, &self.field_y, x, y) })
+    }
+
+    /// The field at the centroid of cell (i, j), where a charge deposited there feels none of its own field
+    pub fn field_in_cell(&self, i: usize, j: usize) -> Pair {
+        Pair { u: self.field_x[[i, j]], v: self.field_y[[i, j]] }
+    }
+
+    /// The force on each of the charges solved for, given by force from the charge and the field at the centroid of
+    /// the cell it is deposited in
+    pub fn forces(&self, geometry: &Geometry, force: impl Fn(&Charge, Pair) -> Pair) -> Result<Vec<Pair>, MaxwellError> {
+        self.charges.iter().map(|charge| {
+            let (i, j) = charge.get_location_on_grid(geometry)?;
+            Ok(force(charge, self.field_in_cell(i, j)))
+        }).collect()
+    }
+}
+
+impl AsRef<GridSolution> for GridSolution {
+    fn as_ref(&self) -> &GridSolution {
+        self
+    }
+}
+
+/// Whether (x, y) lies on the grid, including the boundary cells
+pub(crate) fn check_on_grid(geometry: &Geometry, x: f64, y: f64) -> Result<(), MaxwellError> {
+    if geometry.in_padding_region(x, y) {
+        Ok(())
+    } else {
+        Err(MaxwellError::PointOutOfBounds { x, y })
+    }
+}
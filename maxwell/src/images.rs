@@ -0,0 +1,205 @@
+use serde::{Serialize, Deserialize};
+use crate::Charge;
+use crate::softening::ForceLaw;
+
+/// A conductor filling a half-plane or a disc, which the direct solvers represent by image charges.
+///
+/// Each conductor reflects the charges outside it, so the images are exact for one conductor on its own and
+/// approximate where several conductors face each other. The images of a circle are also only approximate for
+/// softened or screened charges. The uniform field, extended sources and dipoles are not reflected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageConductor {
+    /// The half-plane behind the line through (x, y), whose normal (normal_x, normal_y) points away from the
+    /// conductor. It is grounded; an insulated plane behaves the same, as the charge it gives up spreads to infinity.
+    Plane { x: f64, y: f64, normal_x: f64, normal_y: f64 },
+    /// A cylinder for the logarithmic law, or a sphere for the inverse-square law, which is grounded unless it is
+    /// given a charge, in which case it is insulated and carries that total charge
+    Circle {
+        x: f64,
+        y: f64,
+        radius: f64,
+        #[serde(default)]
+        charge: Option<f64>,
+    },
+}
+
+impl ImageConductor {
+    /// Whether (x, y) lies strictly inside the conductor
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            ImageConductor::Plane { .. } => self.height_above_plane(x, y) < 0.0,
+            ImageConductor::Circle { x: xc, y: yc, radius, .. } => (x - xc).powi(2) + (y - yc).powi(2) < radius * radius,
+        }
+    }
+
+    /// The point on the surface of the conductor closest to (x, y)
+    pub fn closest_surface_point(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            ImageConductor::Plane { .. } => {
+                let (nx, ny) = self.unit_normal();
+                let height = self.height_above_plane(x, y);
+                (x - height * nx, y - height * ny)
+            },
+            ImageConductor::Circle { x: xc, y: yc, radius, .. } => {
+                let r = ((x - xc).powi(2) + (y - yc).powi(2)).sqrt();
+                if r == 0.0 {
+                    return (xc + radius, *yc);
+                }
+                (xc + radius * (x - xc) / r, yc + radius * (y - yc) / r)
+            },
+        }
+    }
+
+    /// Whether the conductor is held at zero potential, with the images of a circle adjusted to bring it there
+    pub fn is_grounded(&self) -> bool {
+        !matches!(self, ImageConductor::Circle { charge: Some(_), .. })
+    }
+
+    /// Visit the images of a charge outside the conductor, which share its softening. A charge inside has none.
+    pub fn for_each_image(&self, charge: &Charge, law: ForceLaw, mut image: impl FnMut(&Charge)) {
+        if self.contains(charge.x, charge.y) {
+            return;
+        }
+        match self {
+            ImageConductor::Plane { .. } => {
+                let (nx, ny) = self.unit_normal();
+                let height = self.height_above_plane(charge.x, charge.y);
+                image(&Charge { x: charge.x - 2.0 * height * nx, y: charge.y - 2.0 * height * ny, charge: -charge.charge, ..charge.clone() });
+            },
+            ImageConductor::Circle { x: xc, y: yc, radius, charge: net_charge } => {
+                let d2 = (charge.x - xc).powi(2) + (charge.y - yc).powi(2);
+                // a sphere's image is weaker than the charge, by the ratio of its radius to the charge's distance
+                let strength = match law {
+                    ForceLaw::Logarithmic => charge.charge,
+                    ForceLaw::InverseSquare => charge.charge * radius / d2.sqrt(),
+                };
+                let inversion = radius * radius / d2;
+                image(&Charge { x: xc + inversion * (charge.x - xc), y: yc + inversion * (charge.y - yc), charge: -strength, ..charge.clone() });
+                // an insulated circle gives back the charge drawn to its surface, spread so as to leave it equipotential
+                if net_charge.is_some() {
+                    image(&Charge { x: *xc, y: *yc, charge: strength, ..charge.clone() });
+                }
+            },
+        }
+    }
+
+    /// The charge at the centre of an insulated circle that stands for its own total charge
+    pub fn net_charge(&self) -> Option<Charge> {
+        match self {
+            ImageConductor::Circle { x, y, charge: Some(charge), .. } => Some(Charge::new(*x, *y, *charge)),
+            _ => None,
+        }
+    }
+
+    fn unit_normal(&self) -> (f64, f64) {
+        match self {
+            ImageConductor::Plane { normal_x, normal_y, .. } => {
+                let length = normal_x.hypot(*normal_y);
+                (normal_x / length, normal_y / length)
+            },
+            ImageConductor::Circle { .. } => (0.0, 0.0),
+        }
+    }
+
+    /// The signed distance of (x, y) from a plane, positive on the side away from the conductor
+    fn height_above_plane(&self, x: f64, y: f64) -> f64 {
+        match self {
+            ImageConductor::Plane { x: x0, y: y0, .. } => {
+                let (nx, ny) = self.unit_normal();
+                (x - x0) * nx + (y - y0) * ny
+            },
+            ImageConductor::Circle { .. } => 0.0,
+        }
+    }
+}
+
+/// The image charges of all the image conductors, kept until the charges or conductors change
+pub struct ImageCharges {
+    /// The images induced by the charges outside the conductors
+    pub induced: Vec<Charge>,
+    /// The charges standing for the total charge of each insulated circle
+    pub net: Vec<Charge>,
+    /// The constant added to the potential of the images so that the grounded conductors are at zero
+    pub potential_offset: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FieldConfiguration, Pair, compute_field_electrostatic_direct, compute_field_electrostatic_per_charge_direct,
+        compute_forces_electrostatic, compute_potential_energy_electrostatic};
+    use crate::builder::{FieldConfigurationBuilder, SolverKind};
+    use crate::softening::SofteningKernel;
+
+    /// Step of the central differences of the energy
+    const STEP: f64 = 1e-3;
+
+    /// Two unsoftened charges of opposite sign outside the conductor, so that the images are exact
+    fn configuration(law: ForceLaw, conductor: ImageConductor) -> FieldConfiguration {
+        let mut config = FieldConfigurationBuilder::new(500.0, 500.0, 100, 100)
+            .solver(SolverKind::ElectrostaticDirect)
+            .force_law(law)
+            .softening_kernel(SofteningKernel::Point)
+            .try_build()
+            .unwrap();
+        config.charges = vec![Charge::new(250.0, 350.0, 1.0), Charge::new(320.0, 300.0, -0.5)];
+        config.charges_changed();
+        config.set_image_conductors_vec(vec![conductor]);
+        config
+    }
+
+    fn energy_with_charge_at(config: &mut FieldConfiguration, index: usize, x: f64, y: f64) -> f64 {
+        config.charges[index].x = x;
+        config.charges[index].y = y;
+        config.charges_changed();
+        compute_potential_energy_electrostatic(config)
+    }
+
+    /// Check the force on each charge against minus the gradient of the energy, taken by central differences
+    fn assert_forces_match_energy(law: ForceLaw, conductor: ImageConductor) {
+        let mut config = configuration(law, conductor.clone());
+        let forces = compute_forces_electrostatic(&config);
+        for (index, Pair { u, v }) in forces.into_iter().enumerate() {
+            let Charge { x, y, .. } = config.charges[index];
+            let gradient_x = (energy_with_charge_at(&mut config, index, x + STEP, y) - energy_with_charge_at(&mut config, index, x - STEP, y)) / (2.0 * STEP);
+            let gradient_y = (energy_with_charge_at(&mut config, index, x, y + STEP) - energy_with_charge_at(&mut config, index, x, y - STEP)) / (2.0 * STEP);
+            energy_with_charge_at(&mut config, index, x, y);
+            let error = (u + gradient_x).hypot(v + gradient_y);
+            assert!(error < 1e-4 * u.hypot(v), "{:?} with {:?}: force ({}, {}) on charge {} but -grad U = ({}, {})", conductor, law, u, v, index, -gradient_x, -gradient_y);
+        }
+    }
+
+    #[test]
+    fn forces_match_energy_with_plane() {
+        let plane = ImageConductor::Plane { x: 0.0, y: 200.0, normal_x: 0.0, normal_y: 1.0 };
+        assert_forces_match_energy(ForceLaw::InverseSquare, plane.clone());
+        assert_forces_match_energy(ForceLaw::Logarithmic, plane);
+    }
+
+    #[test]
+    fn forces_match_energy_with_sphere() {
+        assert_forces_match_energy(ForceLaw::InverseSquare, ImageConductor::Circle { x: 250.0, y: 250.0, radius: 50.0, charge: None });
+        assert_forces_match_energy(ForceLaw::InverseSquare, ImageConductor::Circle { x: 250.0, y: 250.0, radius: 50.0, charge: Some(2.0) });
+    }
+
+    #[test]
+    fn forces_match_energy_with_cylinder() {
+        assert_forces_match_energy(ForceLaw::Logarithmic, ImageConductor::Circle { x: 250.0, y: 250.0, radius: 50.0, charge: None });
+        assert_forces_match_energy(ForceLaw::Logarithmic, ImageConductor::Circle { x: 250.0, y: 250.0, radius: 50.0, charge: Some(2.0) });
+    }
+
+    // With only grounded conductors, every image belongs to one of the charges
+    #[test]
+    fn per_charge_fields_include_images() {
+        let mut config = configuration(ForceLaw::InverseSquare, ImageConductor::Plane { x: 0.0, y: 200.0, normal_x: 0.0, normal_y: 1.0 });
+        config.set_image_conductors_vec(vec![ImageConductor::Plane { x: 0.0, y: 200.0, normal_x: 0.0, normal_y: 1.0 },
+            ImageConductor::Circle { x: 400.0, y: 400.0, radius: 40.0, charge: None }]);
+        for (x, y) in [(280.0, 260.0), (150.0, 420.0), (380.0, 320.0)] {
+            let Pair { u, v } = compute_field_electrostatic_direct(&config, x, y);
+            let per_charge = compute_field_electrostatic_per_charge_direct(&config, x, y);
+            let (sum_u, sum_v) = per_charge.iter().fold((0.0, 0.0), |(su, sv), field| (su + field.u, sv + field.v));
+            assert!((sum_u - u).hypot(sum_v - v) < 1e-9 * u.hypot(v), "per-charge fields sum to ({}, {}), but the field is ({}, {})", sum_u, sum_v, u, v);
+        }
+    }
+}
@@ -2,22 +2,54 @@ use wasm_bindgen::prelude::*;
 use serde::{Serialize, Deserialize};
 #[allow(unused)]
 use serde_wasm_bindgen::{from_value, to_value};
-use console_error_panic_hook;
-use web_sys::console as console;
 use ndarray::Array2;
 use std::fmt::{self, Debug, Formatter};
-use crate::geometry::Geometry;
+use std::cell::RefCell;
+use crate::geometry::{Geometry, BoundaryCondition};
+use crate::softening::{SofteningKernel, ChargeProfile, ForceLaw};
+use crate::units::UnitSystem;
+use crate::builder::{FieldConfigurationBuilder, SolverKind};
+use crate::sources::ExtendedSource;
+use crate::wires::WireElement;
+use crate::magnets::Magnet;
+use crate::dipoles::Dipole;
+use crate::error::{MaxwellError, deserialize};
+use crate::logging::Warning;
+use crate::multigrid::{Conductor, Dielectric, PotentialBoundary};
+use crate::images::{ImageConductor, ImageCharges};
+use crate::vector_potential::{MagneticMaterial, CurrentDensity};
+use crate::grid_solution::GridSolution;
 
 mod stencil;
 mod fourier;
 mod pml;
 mod geometry;
 mod contours;
-
-const FIELD_SCALING: f64 = 20000.0;
-const SOFTEN: f64 = 5.0;
+mod softening;
+mod sources;
+mod wires;
+mod magnets;
+mod dipoles;
+mod dynamics;
+mod nbody;
+mod tree;
+mod ewald;
+mod units;
+mod builder;
+mod error;
+mod logging;
+mod solver;
+mod grid_solution;
+mod spectral;
+mod multigrid;
+mod images;
+mod vector_potential;
+
+/// Contours are not started this close to a point charge, where the potential is too steep to follow
+const CONTOUR_EXCLUSION_RADIUS: f64 = 15.0;
 
 #[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
 pub struct Pair {
     pub u: f64,
     pub v: f64,
@@ -34,28 +66,52 @@ pub fn init_panic_hook() {
 pub struct Charge {
     x: f64,
     y: f64,
-    charge: f64
+    charge: f64,
+    /// Radius of a finite-sized charge; for point profiles, the softening length used by SofteningKernel::PerCharge
+    #[serde(default)]
+    radius: f64,
+    #[serde(default)]
+    profile: ChargeProfile
 }
 
 #[wasm_bindgen]
 impl Charge {
     #[wasm_bindgen(constructor)]
     pub fn new(x: f64, y: f64, charge: f64) -> Charge {
-        Charge { x, y, charge }
+        Charge { x, y, charge, radius: 0.0, profile: ChargeProfile::Point }
+    }
+
+    pub fn new_with_radius(x: f64, y: f64, charge: f64, radius: f64) -> Charge {
+        Charge { x, y, charge, radius, profile: ChargeProfile::Point }
+    }
+
+    pub fn new_finite(x: f64, y: f64, charge: f64, radius: f64, profile: ChargeProfile) -> Charge {
+        Charge { x, y, charge, radius, profile }
     }
 }
 
 impl Charge {
-    pub fn get_location_on_grid(&self, geometry: &Geometry) -> (usize, usize) {
-        let (i,j) = geometry.position_to_cell(self.x, self.y).unwrap();
-        (i, j)
+    pub fn get_location_on_grid(&self, geometry: &Geometry) -> Result<(usize, usize), MaxwellError> {
+        geometry.position_to_cell(self.x, self.y).ok_or(MaxwellError::ChargeOutOfBounds { x: self.x, y: self.y })
+    }
+
+    /// Radius around the charge within which contours should not be started, which covers the charge itself.
+    ///
+    /// Inside a ring the potential is flat, so no contours can be found there at all, and inside a disc or Gaussian
+    /// blob they would be started within the charge.
+    pub fn contour_exclusion_radius(&self) -> f64 {
+        let extent = match self.profile {
+            ChargeProfile::Point => 0.0,
+            ChargeProfile::Disc | ChargeProfile::Ring | ChargeProfile::Gaussian => self.radius,
+        };
+        extent.max(CONTOUR_EXCLUSION_RADIUS)
     }
 }
 
 
 impl Debug for Charge {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "Charge {{ x: {}, y: {}, charge: {} }}", self.x, self.y, self.charge)
+        write!(f, "Charge {{ x: {}, y: {}, charge: {}, radius: {}, profile: {:?} }}", self.x, self.y, self.charge, self.radius, self.profile)
     }
 }
 
@@ -65,7 +121,7 @@ pub struct FieldConfiguration {
     charges: Vec<Charge>,
     charges_at_last_tick: Vec<Charge>, 
     geometry: Geometry,
-    cic_grid: Option<Array2<f64>>,
+    cic_grid: Option<Array2<f64>>, // Includes the charge induced in any conductors and dielectrics at initialisation
     elec_x: Option<Array2<f64>>,
     elec_y: Option<Array2<f64>>,
     current_x: Option<Array2<f64>>,
@@ -75,7 +131,31 @@ pub struct FieldConfiguration {
     stencils: stencil::Stencils,
     charge_normalization: f64,
     elec_uniform_x: f64,
-    elec_uniform_y: f64
+    elec_uniform_y: f64,
+    softening: SofteningKernel,
+    extended_sources: Vec<ExtendedSource>,
+    wires: Vec<WireElement>,
+    magnets: Vec<Magnet>,
+    dipoles: Vec<Dipole>,
+    force_law: ForceLaw,
+    ewald_sum: RefCell<Option<ewald::EwaldSum>>, // Set up lazily, and again whenever the cell or force law changes
+    tree_opening_angle: f64, // Barnes-Hut opening angle for the direct solvers; zero means an exact direct sum
+    charge_tree: RefCell<Option<tree::ChargeTree>>, // Built lazily, and discarded whenever the charges change
+    debye_length: f64, // Screening length of the interaction between charges; zero means unscreened
+    units: UnitSystem,
+    time_per_millisecond: f64, // Simulated time per millisecond of animation
+    boundary_condition: BoundaryCondition,
+    solver: SolverKind,
+    spectral_solution: RefCell<Option<GridSolution>>, // Solved lazily, and again whenever the charges change
+    conductors: Vec<Conductor>,
+    dielectrics: Vec<Dielectric>,
+    potential_boundary: PotentialBoundary,
+    multigrid_solution: RefCell<Option<multigrid::MultigridSolution>>, // Solved lazily, and again whenever the charges change
+    image_conductors: Vec<ImageConductor>,
+    image_charges: RefCell<Option<ImageCharges>>, // Found lazily, and again whenever the charges or conductors change
+    magnetic_materials: Vec<MagneticMaterial>,
+    current_density: Option<CurrentDensity>,
+    vector_potential_solution: RefCell<Option<vector_potential::VectorPotentialSolution>>, // Solved lazily, and again whenever the currents change
 }
 
 pub fn evaluate_grid(field: &Array2<f64>, x: isize, y: isize) -> f64 {
@@ -88,33 +168,34 @@ pub fn evaluate_grid(field: &Array2<f64>, x: isize, y: isize) -> f64 {
     field[[x as usize, y as usize]]
 }
 
-pub fn evaluate_grid_interpolated_or_0(field_config: &FieldConfiguration, grid: &Option<Array2<f64>>, x: f64, y: f64) -> f64 {
+pub fn evaluate_grid_interpolated_or_0(field_config: &FieldConfiguration, grid: &Option<Array2<f64>>, x: f64, y: f64) -> Result<f64, MaxwellError> {
     if let Some(grid) = grid {
         evaluate_grid_interpolated(field_config, grid, x, y)
     } else {
-        0.0
+        Ok(0.0)
     }
 }
 
-pub fn evaluate_grid_interpolated(field_config: &FieldConfiguration, grid: &Array2<f64>, x: f64, y: f64) -> f64 {
-    let nx = grid.shape()[0];
-    let ny = grid.shape()[1];
-
-    let dx = field_config.geometry.delta_x();
-    let dy = field_config.geometry.delta_y();
-
-    assert!(nx == field_config.geometry.nx && ny == field_config.geometry.ny, "Grid size mismatch");
+pub fn evaluate_grid_interpolated(field_config: &FieldConfiguration, grid: &Array2<f64>, x: f64, y: f64) -> Result<f64, MaxwellError> {
+    let (nx, ny) = grid.dim();
+    if nx != field_config.geometry.nx || ny != field_config.geometry.ny {
+        return Err(MaxwellError::GridSizeMismatch { what: "interpolated grid", expected: field_config.geometry.nx * field_config.geometry.ny, found: nx * ny });
+    }
 
     if x < 0.0 || x > field_config.geometry.x_max || y < 0.0 || y > field_config.geometry.y_max {
-        console::log_2(
-            &"Point out of bounds:".into(),
-            &format!("({}, {})", x, y).into()
-        );
-        return 0.0;
+        return Err(MaxwellError::PointOutOfBounds { x, y });
     }
 
-    let (i0, j0) = field_config.geometry.position_to_cell_unclamped(x, y);
-    let (x0, y0) = field_config.geometry.cell_to_centroid(i0 as usize, j0 as usize);
+    Ok(interpolate_grid(&field_config.geometry, grid, x, y))
+}
+
+/// Bilinear interpolation between the cell centroids, treating the grid as zero beyond its edges
+pub fn interpolate_grid(geometry: &Geometry, grid: &Array2<f64>, x: f64, y: f64) -> f64 {
+    let dx = geometry.delta_x();
+    let dy = geometry.delta_y();
+
+    let (i0, j0) = geometry.position_to_cell_unclamped(x, y);
+    let (x0, y0) = geometry.cell_to_centroid_unclamped(i0, j0);
 
     // if x>x0, we interpolate between i and i+1; if x<x0, we interpolate between i-1 and i
     let i1 = if x > x0 { i0 + 1 } else { i0 - 1 };
@@ -140,31 +221,192 @@ pub fn evaluate_grid_interpolated(field_config: &FieldConfiguration, grid: &Arra
 
 #[wasm_bindgen]
 impl FieldConfiguration {
+    /// A configuration with nx/8 boundary cells on every side and the logarithmic force law, whose grid normalisation
+    /// the dynamic solver relies on. Use FieldConfigurationBuilder to make other choices, with validation.
+    ///
+    /// This is a breaking change for the direct solvers, which used to give the inverse-square field $20000 q/r^2$
+    /// and now give the field of a line charge, $4000 q / 2\pi r$. Build with
+    /// `.force_law(ForceLaw::InverseSquare)` for the old direct fields.
     #[wasm_bindgen(constructor)]
     pub fn new(x_max: f64, y_max: f64, nx: usize, ny: usize) -> FieldConfiguration {
-        let geometry = Geometry{x_max: x_max, y_max: y_max, nx: nx, ny: ny, nboundary: nx/8};
-        let geometry_clone = geometry.clone();
-        let cell_area = geometry.delta_x() * geometry.delta_y();
-        let charge_normalization = 4000.0 / cell_area;
-        FieldConfiguration { charges: vec![], charges_at_last_tick: vec![], geometry, 
-            cic_grid: None, elec_x: None, elec_y: None, mag_z: None, mag_z_integral: None, current_x: None, current_y: None,
-            stencils: stencil::Stencils::new(geometry_clone), charge_normalization, 
-            elec_uniform_x: 0.0, elec_uniform_y: 0.0 }
+        Self::from_builder(&FieldConfigurationBuilder::new(x_max, y_max, nx, ny).boundary_width(nx/8).force_law(ForceLaw::Logarithmic))
     }
 
-    pub fn set_charges(&mut self, charges: JsValue) {
-        self.charges = match from_value(charges) {
-            Ok(charges) => charges,
-            Err(err) => {
-                console::log_1(&format!("Error deserializing charges: {:?}", err).into());
-                vec![]
-            }
-        };
+    /// Set the softening kernel from a JS object such as `{type: "plummer", length: 2.0}`
+    pub fn set_softening(&mut self, kernel: JsValue) -> Result<(), JsError> {
+        self.set_softening_kernel(deserialize(kernel, "softening kernel")?);
+        Ok(())
+    }
+
+    pub fn get_softening(&self) -> JsValue {
+        to_value(&self.softening).unwrap()
+    }
+
+    /// Replace the charges, leaving them unchanged and throwing if the new ones cannot be read
+    pub fn set_charges(&mut self, charges: JsValue) -> Result<(), JsError> {
+        self.charges = deserialize(charges, "charges")?;
+        self.charges_changed();
+        Ok(())
+    }
+
+    /// Set the charged segments, arcs and polygons from JS objects such as
+    /// `{type: "segment", x0: 10, y0: 10, x1: 100, y1: 10, line_density: 0.1}`
+    pub fn set_extended_sources(&mut self, sources: JsValue) -> Result<(), JsError> {
+        self.set_extended_sources_vec(deserialize(sources, "extended sources")?);
+        Ok(())
+    }
+
+    /// Set the in-plane current-carrying wires from JS objects such as
+    /// `{type: "circle", x: 200, y: 200, radius: 50, current: 1.0}`
+    pub fn set_wires(&mut self, wires: JsValue) -> Result<(), JsError> {
+        self.wires = deserialize(wires, "wires")?;
+        Ok(())
+    }
+
+    /// Set the permanent magnets from JS objects such as `{type: "dipole", x: 100, y: 100, mx: 1.0, my: 0.0}`
+    pub fn set_magnets(&mut self, magnets: JsValue) -> Result<(), JsError> {
+        self.magnets = deserialize(magnets, "magnets")?;
+        Ok(())
+    }
+
+    /// Set the point electric dipoles from JS objects such as `{x: 100, y: 100, moment: 10.0, angle: 0.0}`
+    pub fn set_dipoles(&mut self, dipoles: JsValue) -> Result<(), JsError> {
+        self.dipoles = deserialize(dipoles, "dipoles")?;
+        Ok(())
+    }
+
+    /// Set the conductors seen by the multigrid solver, from JS objects such as
+    /// `{region: {type: "disc", x: 200, y: 200, radius: 30}, potential: 10.0}`; without a potential they are grounded.
+    /// A conductor given a charge, such as `{region: ..., charge: 0.0}`, is isolated and its potential is solved for.
+    pub fn set_conductors(&mut self, conductors: JsValue) -> Result<(), JsError> {
+        self.set_conductors_vec(deserialize(conductors, "conductors")?);
+        Ok(())
+    }
+
+    /// Set the dielectric regions seen by the multigrid solver, from JS objects such as
+    /// `{region: {type: "polygon", vertices: [[0, 0], [100, 0], [100, 50]]}, permittivity: 4.0}`
+    pub fn set_dielectrics(&mut self, dielectrics: JsValue) -> Result<(), JsError> {
+        self.set_dielectrics_vec(deserialize(dielectrics, "dielectrics")?);
+        Ok(())
+    }
+
+    /// Set the magnetic materials seen by the multigrid magnetostatic solver, from JS objects such as
+    /// `{region: {type: "disc", x: 200, y: 200, radius: 30}, permeability: 1000.0}`
+    pub fn set_magnetic_materials(&mut self, materials: JsValue) -> Result<(), JsError> {
+        self.set_magnetic_materials_vec(deserialize(materials, "magnetic materials")?);
+        Ok(())
+    }
+
+    /// Set the current density seen by the multigrid magnetostatic solver, in current per unit area for each cell of
+    /// the grid in turn (with j varying fastest, for cell (i, j)). Each component may be left empty if it is zero.
+    pub fn set_current_density(&mut self, x: Vec<f64>, y: Vec<f64>, z: Vec<f64>) -> Result<(), JsError> {
+        Ok(self.try_set_current_density(x, y, z)?)
+    }
+
+    /// Set the conducting planes and circles seen by the direct electrostatic solver through image charges, from JS
+    /// objects such as `{type: "plane", x: 0, y: 100, normal_x: 0, normal_y: 1}` or
+    /// `{type: "circle", x: 200, y: 200, radius: 30}`; a circle given a `charge` is insulated rather than grounded
+    pub fn set_image_conductors(&mut self, conductors: JsValue) -> Result<(), JsError> {
+        self.set_image_conductors_vec(deserialize(conductors, "image conductors")?);
+        Ok(())
+    }
+
+    /// Choose between point charges (the inverse-square law) and line charges (the logarithmic law).
+    ///
+    /// The law applies to the direct solvers and to the static solution on the grid, so that the two agree.
+    /// Maxwell's equations in the plane describe line charges, so the dynamic solver should be used with the
+    /// logarithmic law.
+    pub fn set_force_law(&mut self, law: ForceLaw) {
+        self.force_law = law;
+        self.charge_normalization = self.units.grid_charge_scaling(law) / (self.geometry.delta_x() * self.geometry.delta_y());
+        self.stencils.set_force_law(law);
+        // Grid fields were initialised with the old law
+        self.reset_fields();
+    }
+
+    pub fn get_force_law(&self) -> ForceLaw {
+        self.force_law
+    }
+
+    pub fn get_solver_kind(&self) -> SolverKind {
+        self.solver
+    }
+
+    /// Set the accuracy of the direct solvers for large numbers of charges.
+    ///
+    /// With theta > 0, distant groups of charges are replaced by their multipole expansion (Barnes-Hut),
+    /// making each evaluation $O(\log N)$. At 0.5 the root-mean-square force error is a few hundredths of a percent for
+    /// the inverse-square law and a few tenths of a percent for the logarithmic law, whose far field falls off more
+    /// slowly; the default of zero sums over every charge exactly.
+    pub fn set_tree_opening_angle(&mut self, theta: f64) {
+        self.tree_opening_angle = theta.max(0.0);
+    }
+
+    pub fn get_tree_opening_angle(&self) -> f64 {
+        self.tree_opening_angle
+    }
+
+    /// Screen the interaction between charges with the given Debye length, or remove screening with zero.
+    ///
+    /// Screened charges have the Yukawa potential $e^{-r/\lambda}/r$ (or $K_0(r/\lambda)$ for the logarithmic law), in
+    /// the direct solvers and in the static solution on the grid, which then solves the Helmholtz equation. The
+    /// extended sources, dipoles and periodic sums remain unscreened, and the tree is not used while screening is on.
+    /// The dynamic solver evolves the unscreened Maxwell equations from its screened initial conditions.
+    pub fn set_debye_length(&mut self, debye_length: f64) {
+        self.debye_length = debye_length.max(0.0);
+        self.stencils.set_debye_length(self.debye_length);
+        // Grid fields were initialised with the old Green's function
+        self.reset_fields();
+    }
+
+    pub fn get_debye_length(&self) -> f64 {
+        self.debye_length
+    }
+
+    /// Choose the units of all quantities passed in and out; see UnitSystem.
+    ///
+    /// Lengths are not converted, so the geometry, charges and softening should be given in the new units. The
+    /// playback rate of the dynamic solver is reset to the default for the new units.
+    pub fn set_unit_system(&mut self, units: UnitSystem) {
+        self.units = units;
+        self.charge_normalization = units.grid_charge_scaling(self.force_law) / (self.geometry.delta_x() * self.geometry.delta_y());
+        self.time_per_millisecond = units.default_time_per_millisecond(self.geometry.x_max);
+        // Grid fields were initialised in the old units
+        self.reset_fields();
+    }
+
+    pub fn get_unit_system(&self) -> UnitSystem {
+        self.units
+    }
+
+    pub fn get_speed_of_light(&self) -> f64 {
+        self.units.speed_of_light()
+    }
+
+    /// Set how much simulated time passes per millisecond of animation
+    pub fn set_time_per_millisecond(&mut self, time_per_millisecond: f64) {
+        self.time_per_millisecond = time_per_millisecond;
+    }
+
+    pub fn get_time_per_millisecond(&self) -> f64 {
+        self.time_per_millisecond
+    }
+
+    /// The largest timestep that should be passed to tick, a tenth of the light-crossing time of a cell
+    pub fn max_stable_timestep(&self) -> f64 {
+        0.1 * self.geometry.delta_x().min(self.geometry.delta_y()) / self.units.speed_of_light()
+    }
+
+    pub fn get_charges(&self) -> JsValue {
+        to_value(&self.charges).unwrap()
     }
 
     pub fn set_uniform_field(&mut self, x: f64, y: f64) {
         self.elec_uniform_x = x;
         self.elec_uniform_y = y;
+        // the multigrid solutions take the uniform field from their boundary conditions
+        self.multigrid_solution.replace(None);
+        self.vector_potential_solution.replace(None);
     }
 
     pub fn get_uniform_field(&self) -> Pair {
@@ -179,31 +421,83 @@ impl FieldConfiguration {
         self.mag_z_integral = None;
         self.current_x = None;
         self.current_y = None;
+        self.spectral_solution.replace(None);
+        self.multigrid_solution.replace(None);
+        self.vector_potential_solution.replace(None);
+        // the potential offset of the images depends on the interaction as well as the charges
+        self.image_charges.replace(None);
     }
 
 
     pub fn make_cic_grid(&mut self) {
-        self.cic_grid = Some(Array2::<f64>::zeros((self.geometry.nx, self.geometry.ny)));
-        
-        if let Some(ref mut grid) = self.cic_grid {
-            for charge in &self.charges {
-                if charge.x<0.0 || charge.x>self.geometry.x_max || charge.y<0.0 || charge.y>self.geometry.y_max {
-                    console::log_1(&format!("Charge out of bounds: {:?}", charge).into());
-                    continue;
-                }
-                let (i,j) = charge.get_location_on_grid(&self.geometry);
-                self.stencils.add_softened_point(grid, i, j, charge.charge * self.charge_normalization);
+        self.cic_grid = Some(self.charge_density_on_grid());
+    }
+
+    pub fn evaluate_cic_grid_interpolated(&self, x: f64, y: f64) -> Result<f64, JsError> {
+        Ok(evaluate_grid_interpolated_or_0(self, &self.cic_grid, x, y)?)
+    }
+
+    /// Evolve the fields by one timestep, throwing if the charges cannot be moved on the grid
+    pub fn tick(&mut self, delta_t: f64) -> Result<(), JsError> {
+        Ok(self.try_tick(delta_t)?)
+    }
+}
+
+impl FieldConfiguration {
+    /// The softened charges and the extended sources deposited on the grid, in the normalisation of the Poisson solve
+    pub fn charge_density_on_grid(&self) -> Array2<f64> {
+        let mut grid = self.point_charge_density_on_grid();
+        for source in &self.extended_sources {
+            source.deposit(&mut grid, &self.geometry, &self.stencils, &self.softening, self.charge_normalization);
+        }
+        grid
+    }
+
+    /// The softened charges alone deposited on the grid, which are also the out-of-plane currents of the
+    /// magnetostatic solvers
+    pub fn point_charge_density_on_grid(&self) -> Array2<f64> {
+        let mut grid = Array2::<f64>::zeros((self.geometry.nx, self.geometry.ny));
+        for charge in &self.charges {
+            if charge.x<0.0 || charge.x>self.geometry.x_max || charge.y<0.0 || charge.y>self.geometry.y_max {
+                Warning::ChargeOutsideGrid { x: charge.x, y: charge.y }.report();
+                continue;
             }
+            let Ok((i,j)) = charge.get_location_on_grid(&self.geometry) else { continue };
+            self.stencils.add_softened_point(&mut grid, i, j, charge.charge * self.charge_normalization,
+                &self.softening.for_charge(charge));
         }
+        grid
     }
 
-    pub fn initialize_on_constraints(&mut self) {
+    pub fn initialize_on_constraints(&mut self) -> Result<(), MaxwellError> {
         self.make_cic_grid();
 
         let mut elec_y: Array2<f64> = self.cic_grid.as_ref().unwrap().clone();
         let mut elec_x = elec_y.clone();
-        self.stencils.apply(&mut elec_y, stencil::StencilType::GradYDelSquaredInv, stencil::DifferenceType::Forward);
-        self.stencils.apply(&mut elec_x, stencil::StencilType::GradXDelSquaredInv, stencil::DifferenceType::Forward);
+        if self.conductors.is_empty() && self.dielectrics.is_empty() {
+            self.stencils.apply(&mut elec_y, stencil::StencilType::GradYDelSquaredInv, stencil::DifferenceType::Forward)?;
+            self.stencils.apply(&mut elec_x, stencil::StencilType::GradXDelSquaredInv, stencil::DifferenceType::Forward)?;
+        } else {
+            // Fourier inversion cannot represent the regions, so start from the multigrid solution instead. The
+            // dynamic solver then evolves the fields in vacuum, without the regions, so the charge induced on the
+            // conductors and in the dielectrics stays where the multigrid solution put it.
+            let mut potential = multigrid::MultigridSolution::new(self, None)?.as_ref().potential_grid().clone();
+            for ((i, j), p) in potential.indexed_iter_mut() {
+                let (x, y) = self.geometry.cell_to_centroid(i, j);
+                *p -= (x - self.geometry.x_max / 2.0) * self.elec_uniform_x + (y - self.geometry.y_max / 2.0) * self.elec_uniform_y;
+            }
+            self.stencils.apply_grad(&potential, &mut elec_x, stencil::StencilType::GradX, stencil::DifferenceType::Forward)?;
+            self.stencils.apply_grad(&potential, &mut elec_y, stencil::StencilType::GradY, stencil::DifferenceType::Forward)?;
+
+            // Gauss's law holds for the total charge, including the frozen induced charge, which is the divergence of
+            // the field with the same differences as the dynamic solver
+            let mut density = Array2::<f64>::zeros((self.geometry.nx, self.geometry.ny));
+            let mut divergence_y = density.clone();
+            self.stencils.apply_grad(&elec_x, &mut density, stencil::StencilType::GradX, stencil::DifferenceType::Backward)?;
+            self.stencils.apply_grad(&elec_y, &mut divergence_y, stencil::StencilType::GradY, stencil::DifferenceType::Backward)?;
+            density += &divergence_y;
+            self.cic_grid = Some(density);
+        }
 
         self.elec_y = Some(elec_y);
         self.elec_x = Some(elec_x);
@@ -214,14 +508,14 @@ impl FieldConfiguration {
         self.current_y = Some(Array2::<f64>::zeros((self.geometry.nx, self.geometry.ny)));
       
         self.charges_at_last_tick = self.charges.clone();
-        
+        Ok(())
     }
 
 
-    pub fn ensure_initialized(&mut self) {
+    pub fn ensure_initialized(&mut self) -> Result<(), MaxwellError> {
         // Basic case: initialize if we have no fields yet
         if self.elec_y.is_none() {
-            self.initialize_on_constraints();
+            self.initialize_on_constraints()?;
         }
 
         // During dynamic evolution, the total number of charges must remain constant and the strength of each charge must remain constant.
@@ -240,15 +534,14 @@ impl FieldConfiguration {
         }
 
         if constraint_violation {
-            self.initialize_on_constraints();
+            self.initialize_on_constraints()?;
         }
+        Ok(())
     }
 
-    pub fn evaluate_cic_grid_interpolated(&mut self, x: f64, y: f64) -> f64 {
-        evaluate_grid_interpolated_or_0(self, &self.cic_grid, x, y)
-    }
-
-    pub fn make_currents(&mut self, delta_t: f64) {
+    /// Deposit the currents of the charges that have moved since the last tick, which must have the same number and
+    /// strengths of charges as now
+    pub fn make_currents(&mut self, delta_t: f64) -> Result<(), MaxwellError> {
         // Compute the current density from the charge density and the charge density at the last tick.
         // 
         // This is calculated by finding an approximate straight line between the locations of the charges
@@ -260,17 +553,17 @@ impl FieldConfiguration {
         jy.fill(0.0);
     
         if self.charges.len() != self.charges_at_last_tick.len() {
-            panic!("Number of charges changed since last tick");
+            return Err(MaxwellError::ChargeCountChanged { before: self.charges_at_last_tick.len(), after: self.charges.len() });
         }
 
-        for (charge_earlier, charge_now) in self.charges_at_last_tick.iter().zip(self.charges.iter()) {
+        for (index, (charge_earlier, charge_now)) in self.charges_at_last_tick.iter().zip(self.charges.iter()).enumerate() {
 
             if charge_earlier.charge != charge_now.charge {
-                panic!("Strength of a charge changed since last tick");
+                return Err(MaxwellError::ChargeStrengthChanged { index, before: charge_earlier.charge, after: charge_now.charge });
             }
 
-            let (i_earlier, j_earlier) = charge_earlier.get_location_on_grid(&self.geometry);
-            let (i_now, j_now) = charge_now.get_location_on_grid(&self.geometry);
+            let (i_earlier, j_earlier) = charge_earlier.get_location_on_grid(&self.geometry)?;
+            let (i_now, j_now) = charge_now.get_location_on_grid(&self.geometry)?;
 
             if i_earlier == i_now && j_earlier == j_now {
                 continue;
@@ -297,26 +590,28 @@ impl FieldConfiguration {
             // On the assumption that the path length in a single frame is small, we just go along the x then the y direction rather
             // than anything fancier than that.
 
+            let kernel = self.softening.for_charge(charge_now);
+
             for i in i_min..i_max {
-                self.stencils.add_softened_point(jx, i, j_earlier, x_current_density);
+                self.stencils.add_softened_point(jx, i, j_earlier, x_current_density, &kernel);
             }
 
             for j in j_min..j_max {
-                self.stencils.add_softened_point(jy, i_now, j, y_current_density);
+                self.stencils.add_softened_point(jy, i_now, j, y_current_density, &kernel);
             }
             
 
         }
         
-
+        Ok(())
     }
     
     /// Evolve the fields by one timestep
-    pub fn tick(&mut self, delta_t: f64) {
-        self.ensure_initialized();
+    pub fn try_tick(&mut self, delta_t: f64) -> Result<(), MaxwellError> {
+        self.ensure_initialized()?;
 
         // compute the currents from any motion in the charges. 
-        self.make_currents(delta_t);
+        self.make_currents(delta_t)?;
 
         // save the charges so we can compute the currents next time
         self.charges_at_last_tick = self.charges.clone();
@@ -330,19 +625,26 @@ impl FieldConfiguration {
         let current_x = self.current_x.as_ref().unwrap();
         let current_y = self.current_y.as_ref().unwrap();
 
-        if (delta_t / self.geometry.delta_x()).abs() > 0.1 {
-            console::log_1(&(format!("Warning: timestep is large compared to grid cell size: {}", delta_t / self.geometry.delta_x())).into());
+        // The grid stores $cB$ in SI units, so that the equations take the same form in every unit system
+        let c = self.units.speed_of_light();
+
+        let courant_number = (c * delta_t / self.geometry.delta_x()).abs();
+        if courant_number > 0.1 {
+            Warning::LargeTimestep { courant_number }.report();
         } 
 
 
         // Evolve Bz field first, to get it from half a tick behind to half a tick ahead of the E field
+        let absorbing = self.boundary_condition.is_absorbing();
+
         for (i, j, sigma_x, sigma_y) in pml::pml_iterator_from_geometry(&self.geometry) {
+            let (sigma_x, sigma_y) = if absorbing { (sigma_x, sigma_y) } else { (0.0, 0.0) };
             
-            let d_elec_x_dy = self.stencils.evaluate(&elec_x, i, j, &stencil::StencilType::GradY, &stencil::DifferenceType::Forward);
-            let d_elec_y_dx = self.stencils.evaluate(&elec_y, i, j, &stencil::StencilType::GradX, &stencil::DifferenceType::Forward);
+            let d_elec_x_dy = self.stencils.evaluate(elec_x, i, j, &stencil::StencilType::GradY, &stencil::DifferenceType::Forward)?;
+            let d_elec_y_dx = self.stencils.evaluate(elec_y, i, j, &stencil::StencilType::GradX, &stencil::DifferenceType::Forward)?;
 
             // dB_z/dt = -dE_x/dy + dE_y/dx
-            mag_z[[i,j]] += (d_elec_x_dy - d_elec_y_dx) * delta_t;
+            mag_z[[i,j]] += c * (d_elec_x_dy - d_elec_y_dx) * delta_t;
             
             // Now add the PML damping terms. This is a non-physical term that damps the fields near the boundary to 
             // mimic vacuum BCs. This is explained in the following sources:
@@ -369,10 +671,11 @@ impl FieldConfiguration {
 
         // Now the B field is half a tick ahead, so update the E field a tick to get ahead again
         for (i, j, sigma_x, sigma_y) in pml::pml_iterator_from_geometry(&self.geometry) {
-            let d_magz_dy = self.stencils.evaluate(&mag_z, i, j, &stencil::StencilType::GradY, &stencil::DifferenceType::Backward);
-            let d_magz_dx = self.stencils.evaluate(&mag_z, i, j, &stencil::StencilType::GradX, &stencil::DifferenceType::Backward);
-            elec_x[[i,j]] += (d_magz_dy - current_x[[i,j]]) * delta_t;
-            elec_y[[i,j]] += (-d_magz_dx - current_y[[i,j]]) * delta_t;
+            let (sigma_x, sigma_y) = if absorbing { (sigma_x, sigma_y) } else { (0.0, 0.0) };
+            let d_magz_dy = self.stencils.evaluate(mag_z, i, j, &stencil::StencilType::GradY, &stencil::DifferenceType::Backward)?;
+            let d_magz_dx = self.stencils.evaluate(mag_z, i, j, &stencil::StencilType::GradX, &stencil::DifferenceType::Backward)?;
+            elec_x[[i,j]] += (c * d_magz_dy - current_x[[i,j]]) * delta_t;
+            elec_y[[i,j]] += (-c * d_magz_dx - current_y[[i,j]]) * delta_t;
             
             // PML terms commented out, see above -- just a damping for now
             let pml_x_term = -sigma_y * elec_x[[i, j]]; // + sigma_x * self.stencils.evaluate(&mag_z_integral, i, j, &stencil::StencilType::GradY, &stencil::DifferenceType::Backward);
@@ -381,20 +684,347 @@ impl FieldConfiguration {
             elec_y[[i,j]] += pml_y_term*delta_t;
         }
 
+        Ok(())
     }
-}
 
-impl FieldConfiguration {
-    pub fn evaluate_elec_interpolated(&mut self, x: f64, y: f64) -> (f64, f64) {
-        self.ensure_initialized();
-        let elec_x = evaluate_grid_interpolated_or_0(self, &self.elec_x, x, y);
-        let elec_y = evaluate_grid_interpolated_or_0(self, &self.elec_y, x, y);
-        (elec_x + self.elec_uniform_x, elec_y + self.elec_uniform_y)
+    /// Set the softening kernel used by both the direct solvers and deposition onto the grid
+    pub fn set_softening_kernel(&mut self, kernel: SofteningKernel) {
+        self.softening = kernel;
+        self.stencils.set_softening(kernel);
+        self.charges_changed();
+        // Grid fields were initialised with the old kernel
+        self.reset_fields();
+    }
+
+    pub fn softening_kernel(&self) -> SofteningKernel {
+        self.softening
+    }
+
+    /// Assemble a configuration from the builder's choices, which are assumed to have been validated
+    pub(crate) fn from_builder(builder: &FieldConfigurationBuilder) -> FieldConfiguration {
+        let geometry = Geometry { x_max: builder.x_max, y_max: builder.y_max, nx: builder.nx, ny: builder.ny, boundary: builder.boundary };
+        let force_law = builder.force_law.unwrap_or(builder.solver.default_force_law());
+        let units = builder.units;
+        let charge_normalization = units.grid_charge_scaling(force_law) / (geometry.delta_x() * geometry.delta_y());
+        let softening = builder.softening;
+        let mut stencils = stencil::Stencils::new(geometry.clone(), softening, force_law, builder.stencil_order);
+        stencils.set_isolated(builder.boundary_condition == BoundaryCondition::Isolated);
+        FieldConfiguration { charges: vec![], charges_at_last_tick: vec![], geometry,
+            cic_grid: None, elec_x: None, elec_y: None, mag_z: None, mag_z_integral: None, current_x: None, current_y: None,
+            stencils, charge_normalization,
+            elec_uniform_x: 0.0, elec_uniform_y: 0.0, softening, extended_sources: vec![], wires: vec![], magnets: vec![], dipoles: vec![],
+            force_law, ewald_sum: RefCell::new(None), tree_opening_angle: 0.0, charge_tree: RefCell::new(None), debye_length: 0.0,
+            units, time_per_millisecond: units.default_time_per_millisecond(builder.x_max),
+            boundary_condition: builder.boundary_condition, solver: builder.solver, spectral_solution: RefCell::new(None),
+            conductors: vec![], dielectrics: vec![], potential_boundary: builder.potential_boundary, multigrid_solution: RefCell::new(None),
+            image_conductors: vec![], image_charges: RefCell::new(None), magnetic_materials: vec![], current_density: None, vector_potential_solution: RefCell::new(None) }
+    }
+
+    /// The overall strength of the interaction for the current force law and units
+    fn field_scaling(&self) -> f64 {
+        self.units.field_scaling(self.force_law)
+    }
+
+    /// The overall strength of the H field of the magnets, which is their B field over $\mu_0$ outside them
+    fn h_field_scaling(&self) -> f64 {
+        self.units.magnet_scaling(self.force_law) / self.units.vacuum_permeability()
+    }
+
+    /// The overall strength of the $B_z$ of in-plane wires, which always follow the inverse-square law
+    fn wire_field_scaling(&self) -> f64 {
+        self.units.field_scaling(ForceLaw::InverseSquare) * self.units.current_field_factor()
+    }
+
+    /// Call f with the grid solution kept in cache, solving again only if the charges have changed since it was
+    /// found. The previous solution is passed to solve, as the iterative solvers start from it.
+    fn with_grid_solution<S: AsRef<GridSolution>, T>(&self, cache: &RefCell<Option<S>>, solve: impl FnOnce(Option<S>) -> Result<S, MaxwellError>,
+        f: impl FnOnce(&S) -> T) -> Result<T, MaxwellError> {
+        let mut solution = cache.borrow_mut();
+        if !solution.as_ref().is_some_and(|solution| solution.as_ref().is_for(&self.charges)) {
+            let previous = solution.take();
+            *solution = Some(solve(previous)?);
+        }
+        Ok(f(solution.as_ref().unwrap()))
+    }
+
+    /// Call f with the spectral solution for the current charges
+    fn with_spectral_solution<T>(&self, f: impl FnOnce(&GridSolution) -> T) -> Result<T, MaxwellError> {
+        self.with_grid_solution(&self.spectral_solution, |_| spectral::solve(self), f)
+    }
+
+    /// The potential of the charges and extended sources from the spectral solution, together with the uniform field
+    pub fn spectral_potential(&self, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        let uniform = (x-self.geometry.x_max/2.0) * self.elec_uniform_x + (y - self.geometry.y_max/2.0) * self.elec_uniform_y;
+        Ok(self.with_spectral_solution(|solution| solution.potential(&self.geometry, x, y))?? + uniform)
+    }
+
+    /// The field of the charges and extended sources from the spectral solution, together with the uniform field
+    pub fn spectral_field(&self, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        let Pair { u, v } = self.with_spectral_solution(|solution| solution.field(&self.geometry, x, y))??;
+        Ok(Pair { u: u + self.elec_uniform_x, v: v + self.elec_uniform_y })
+    }
+
+    /// The force on each charge from the spectral solution, taken at the centroid of the cell it is deposited in so
+    /// that it feels none of its own field
+    pub fn spectral_forces(&self) -> Result<Vec<Pair>, MaxwellError> {
+        self.with_spectral_solution(|solution| solution.forces(&self.geometry, |charge, Pair { u, v }| {
+            Pair { u: charge.charge * (u + self.elec_uniform_x), v: charge.charge * (v + self.elec_uniform_y) }
+        }))?
+    }
+
+    fn with_multigrid_solution<T>(&self, f: impl FnOnce(&multigrid::MultigridSolution) -> T) -> Result<T, MaxwellError> {
+        self.with_grid_solution(&self.multigrid_solution, |previous| multigrid::MultigridSolution::new(self, previous), f)
+    }
+
+    /// The potential of the charges, extended sources and uniform field from the multigrid solution
+    pub fn multigrid_potential(&self, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        self.with_multigrid_solution(|solution| solution.as_ref().potential(&self.geometry, x, y))?
+    }
+
+    /// The field of the charges, extended sources and uniform field from the multigrid solution
+    pub fn multigrid_field(&self, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        self.with_multigrid_solution(|solution| solution.as_ref().field(&self.geometry, x, y))?
+    }
+
+    /// The force on each charge from the multigrid solution, taken at the centroid of the cell it is deposited in
+    pub fn multigrid_forces(&self) -> Result<Vec<Pair>, MaxwellError> {
+        self.with_multigrid_solution(|solution| solution.as_ref().forces(&self.geometry, |charge, Pair { u, v }| {
+            Pair { u: charge.charge * u, v: charge.charge * v }
+        }))?
+    }
+
+    /// The potential of each conductor, in the order they were set, including those of the isolated conductors found
+    /// by the multigrid solution
+    pub fn conductor_potentials(&self) -> Result<Vec<f64>, MaxwellError> {
+        self.with_multigrid_solution(|solution| solution.conductor_potentials().to_vec())
+    }
+
+    /// The potential and charge of each conductor, in the order they were set, from the multigrid solution
+    pub fn induced_charges(&self) -> Result<Vec<multigrid::InducedCharge>, MaxwellError> {
+        self.with_multigrid_solution(|solution| solution.induced_charges(&self.geometry))
+    }
+
+    fn with_vector_potential_solution<T>(&self, f: impl FnOnce(&vector_potential::VectorPotentialSolution) -> T) -> Result<T, MaxwellError> {
+        self.with_grid_solution(&self.vector_potential_solution, |previous| vector_potential::VectorPotentialSolution::new(self, previous), f)
+    }
+
+    /// The vector potential $A_z$ of the out-of-plane currents and uniform field, whose contours are field lines
+    pub fn vector_potential(&self, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        self.with_vector_potential_solution(|solution| solution.as_ref().potential(&self.geometry, x, y))?
+    }
+
+    /// The in-plane magnetic field of the out-of-plane currents and uniform field from the vector potential
+    pub fn vector_potential_field(&self, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        self.with_vector_potential_solution(|solution| solution.as_ref().field(&self.geometry, x, y))?
+    }
+
+    /// The out-of-plane magnetic field $B_z$ of the in-plane current density
+    pub fn vector_potential_field_z(&self, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        self.with_vector_potential_solution(|solution| solution.field_z(&self.geometry, x, y))?
+    }
+
+    /// The force on each out-of-plane current from the in-plane field, taken at the centroid of the cell it is
+    /// deposited in, using the same convention as compute_forces_magnetostatic
+    pub fn vector_potential_forces(&self) -> Result<Vec<Pair>, MaxwellError> {
+        let factor = self.units.current_force_factor();
+        self.with_vector_potential_solution(|solution| solution.as_ref().forces(&self.geometry, |charge, Pair { u, v }| {
+            Pair { u: factor * v * charge.charge, v: -factor * u * charge.charge }
+        }))?
+    }
+
+    /// The capacitance matrix of the conductors, in the order they were set; see multigrid::capacitance_matrix
+    pub fn capacitance_matrix(&self) -> Result<Vec<Vec<f64>>, MaxwellError> {
+        multigrid::capacitance_matrix(self)
+    }
+
+    /// Must be called after moving or changing charges in place, so that the charge tree is rebuilt
+    pub fn charges_changed(&mut self) {
+        self.charge_tree.replace(None);
+        self.image_charges.replace(None);
+    }
+
+    /// Visit the contribution of every charge (other than exclude) to a quantity evaluated at (x, y).
+    ///
+    /// Charges are passed to near one at a time, unless the tree is in use, in which case distant groups of charges
+    /// are passed to far as a single tree node.
+    fn for_each_charge_contribution(&self, x: f64, y: f64, exclude: Option<usize>, mut near: impl FnMut(&Charge), far: impl FnMut(&tree::TreeNode)) {
+        // the multipole expansions are for the unscreened interaction
+        if self.tree_opening_angle <= 0.0 || self.debye_length > 0.0 {
+            for (index, charge) in self.charges.iter().enumerate() {
+                if Some(index) != exclude {
+                    near(charge);
+                }
+            }
+            return;
+        }
+        let mut charge_tree = self.charge_tree.borrow_mut();
+        let charge_tree = charge_tree.get_or_insert_with(|| tree::ChargeTree::new(&self.charges, &self.softening));
+        charge_tree.walk(x, y, self.tree_opening_angle, |index| {
+            if Some(index) != exclude {
+                near(&self.charges[index]);
+            }
+        }, far);
+    }
+
+    pub fn set_extended_sources_vec(&mut self, sources: Vec<ExtendedSource>) {
+        self.extended_sources = sources;
+        // Extended sources are static, so changing them violates the constraints just like changing a charge
+        self.reset_fields();
+    }
+
+    pub fn extended_sources(&self) -> &[ExtendedSource] {
+        &self.extended_sources
+    }
+
+    pub fn set_wires_vec(&mut self, wires: Vec<WireElement>) {
+        self.wires = wires;
+    }
+
+    pub fn wires(&self) -> &[WireElement] {
+        &self.wires
+    }
+
+    pub fn set_magnets_vec(&mut self, magnets: Vec<Magnet>) {
+        self.magnets = magnets;
+    }
+
+    pub fn magnets(&self) -> &[Magnet] {
+        &self.magnets
+    }
+
+    pub fn set_dipoles_vec(&mut self, dipoles: Vec<Dipole>) {
+        self.dipoles = dipoles;
+    }
+
+    pub fn dipoles(&self) -> &[Dipole] {
+        &self.dipoles
+    }
+
+    pub fn set_conductors_vec(&mut self, conductors: Vec<Conductor>) {
+        self.conductors = conductors;
+        self.reset_fields();
+    }
+
+    pub fn conductors(&self) -> &[Conductor] {
+        &self.conductors
+    }
+
+    pub fn set_dielectrics_vec(&mut self, dielectrics: Vec<Dielectric>) {
+        self.dielectrics = dielectrics;
+        self.reset_fields();
+    }
+
+    pub fn dielectrics(&self) -> &[Dielectric] {
+        &self.dielectrics
+    }
+
+    pub fn set_magnetic_materials_vec(&mut self, materials: Vec<MagneticMaterial>) {
+        self.magnetic_materials = materials;
+        self.reset_fields();
+    }
+
+    pub fn magnetic_materials(&self) -> &[MagneticMaterial] {
+        &self.magnetic_materials
+    }
+
+    /// Set the current density from one value per cell for each component, as for set_current_density
+    pub fn try_set_current_density(&mut self, x: Vec<f64>, y: Vec<f64>, z: Vec<f64>) -> Result<(), MaxwellError> {
+        let shape = (self.geometry.nx, self.geometry.ny);
+        let to_grid = |values: Vec<f64>, what: &'static str| -> Result<Array2<f64>, MaxwellError> {
+            if values.is_empty() {
+                return Ok(Array2::<f64>::zeros(shape));
+            }
+            let found = values.len();
+            Array2::from_shape_vec(shape, values).map_err(|_| MaxwellError::GridSizeMismatch { what, expected: shape.0 * shape.1, found })
+        };
+        let all_empty = x.is_empty() && y.is_empty() && z.is_empty();
+        let density = CurrentDensity { x: to_grid(x, "current density x")?, y: to_grid(y, "current density y")?, z: to_grid(z, "current density z")? };
+        self.current_density = (!all_empty).then_some(density);
+        self.reset_fields();
+        Ok(())
+    }
+
+    pub fn current_density(&self) -> Option<&CurrentDensity> {
+        self.current_density.as_ref()
+    }
+
+    pub fn set_image_conductors_vec(&mut self, conductors: Vec<ImageConductor>) {
+        self.image_conductors = conductors;
+        self.image_charges.replace(None);
+    }
+
+    pub fn image_conductors(&self) -> &[ImageConductor] {
+        &self.image_conductors
+    }
+
+    /// Call f with the image charges for the current charges and image conductors, finding them again only if
+    /// either has changed since they were last found
+    fn with_image_charges<T>(&self, f: impl FnOnce(&ImageCharges) -> T) -> T {
+        let mut image_charges = self.image_charges.borrow_mut();
+        let image_charges = image_charges.get_or_insert_with(|| ImageCharges {
+            induced: self.induced_image_charges(),
+            net: self.net_image_charges(),
+            potential_offset: self.image_potential_offset(),
+        });
+        f(image_charges)
+    }
+
+    /// Call f with the Ewald sum for the grid's cell and the current force law, which depends on nothing else
+    fn with_ewald_sum<T>(&self, f: impl FnOnce(&ewald::EwaldSum) -> T) -> T {
+        let (x_max, y_max) = (self.geometry.x_max, self.geometry.y_max);
+        let mut ewald_sum = self.ewald_sum.borrow_mut();
+        if !ewald_sum.as_ref().is_some_and(|ewald_sum| ewald_sum.is_for(x_max, y_max, self.force_law)) {
+            *ewald_sum = Some(ewald::EwaldSum::new(x_max, y_max, self.force_law));
+        }
+        f(ewald_sum.as_ref().unwrap())
+    }
+
+    /// The image charges induced in the image conductors by the charges outside them
+    fn induced_image_charges(&self) -> Vec<Charge> {
+        let mut images = vec![];
+        for conductor in &self.image_conductors {
+            for charge in &self.charges {
+                conductor.for_each_image(charge, self.force_law, |image| images.push(image.clone()));
+            }
+        }
+        images
+    }
+
+    /// The image charges standing for the total charge of each insulated image conductor
+    fn net_image_charges(&self) -> Vec<Charge> {
+        self.image_conductors.iter().filter_map(|conductor| conductor.net_charge()).collect()
+    }
+
+    /// The constant added to the potential of the images so that the grounded image conductors are at zero. Only a
+    /// grounded circle needs it, and then only for the logarithmic law or softened charges.
+    fn image_potential_offset(&self) -> f64 {
+        let mut offset = 0.0;
+        for conductor in self.image_conductors.iter().filter(|conductor| conductor.is_grounded()) {
+            for charge in self.charges.iter().filter(|charge| !conductor.contains(charge.x, charge.y)) {
+                let (x, y) = conductor.closest_surface_point(charge.x, charge.y);
+                offset -= compute_potential_electrostatic_direct_one_charge(self, charge, x, y);
+                conductor.for_each_image(charge, self.force_law, |image| {
+                    offset -= compute_potential_electrostatic_direct_one_charge(self, image, x, y);
+                });
+            }
+        }
+        offset
+    }
+
+    pub fn in_image_conductor(&self, x: f64, y: f64) -> bool {
+        self.image_conductors.iter().any(|conductor| conductor.contains(x, y))
+    }
+
+    pub fn evaluate_elec_interpolated(&mut self, x: f64, y: f64) -> Result<(f64, f64), MaxwellError> {
+        self.ensure_initialized()?;
+        let elec_x = evaluate_grid_interpolated_or_0(self, &self.elec_x, x, y)?;
+        let elec_y = evaluate_grid_interpolated_or_0(self, &self.elec_y, x, y)?;
+        Ok((elec_x + self.elec_uniform_x, elec_y + self.elec_uniform_y))
     }
 
     pub fn distance_to_closest_charge(&self, x: f64, y: f64) -> f64 {
-        if(self.charges.len() == 0) {
-            return 1.0e9;
+        if self.charges.is_empty() {
+            1.0e9
         } else {
             let closest_charge = self.closest_charge(x, y).unwrap();
             let dx = x - closest_charge.x;
@@ -403,6 +1033,15 @@ impl FieldConfiguration {
         }
     }
 
+    /// Whether (x, y) lies within the contour exclusion radius of any charge
+    pub fn in_contour_exclusion_zone(&self, x: f64, y: f64) -> bool {
+        self.charges.iter().any(|charge| {
+            let dx = x - charge.x;
+            let dy = y - charge.y;
+            dx*dx + dy*dy < charge.contour_exclusion_radius().powi(2)
+        })
+    }
+
     pub fn closest_charge(&self, x: f64, y: f64) -> Option<&Charge> {
         let mut closest_charge: Option<&Charge> = None;
         let mut closest_distance: f64 = 1.0e9;
@@ -419,52 +1058,211 @@ impl FieldConfiguration {
     }
 }
 
+/// The potential at (x, y) of the charges, extended sources, dipoles and uniform field, together with the images in
+/// any image conductors. Inside an image conductor it is the potential at the closest point on its surface.
 #[wasm_bindgen]
 pub fn compute_potential_electrostatic_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
-    let mut potential: f64 = (x-field_configuration.geometry.x_max/2.0) * field_configuration.elec_uniform_x + (y - field_configuration.geometry.y_max/2.0) * field_configuration.elec_uniform_y;
-    for charge in &field_configuration.charges {
-        let dx = x - charge.x;
-        let dy = y - charge.y;
-        let r = (dx * dx + dy * dy + SOFTEN).sqrt();
-        potential -= FIELD_SCALING * charge.charge / r;
-    }
-    potential
+    let (x, y) = match field_configuration.image_conductors.iter().find(|conductor| conductor.contains(x, y)) {
+        Some(conductor) => conductor.closest_surface_point(x, y),
+        None => (x, y),
+    };
+    let mut potential = compute_potential_electrostatic_external(field_configuration, x, y) + compute_potential_images(field_configuration, x, y);
+    let mut far_potential = 0.0;
+    field_configuration.for_each_charge_contribution(x, y, None,
+        |charge| potential += compute_potential_electrostatic_direct_one_charge(field_configuration, charge, x, y),
+        |node| far_potential -= field_configuration.field_scaling() * node.potential(x, y, field_configuration.force_law));
+    potential + far_potential
 }
 
-fn compute_field_electrostatic_direct_one_charge(charge: &Charge, x: f64, y: f64) -> Pair {
+fn compute_potential_electrostatic_direct_one_charge(field_configuration: &FieldConfiguration, charge: &Charge, x: f64, y: f64) -> f64 {
     let dx = x - charge.x;
     let dy = y - charge.y;
-    let r = (dx * dx + dy * dy + SOFTEN).sqrt();
-    let k = FIELD_SCALING * charge.charge;
-    Pair { u: k * dx / (r * r * r), v: k * dy / (r * r * r) }
+    let kernel = field_configuration.softening.for_charge(charge);
+    -field_configuration.field_scaling() * charge.charge * kernel.screened_potential_factor(field_configuration.force_law, dx * dx + dy * dy, field_configuration.debye_length)
+}
+
+/// The potential of the image charges in the image conductors, with the grounded conductors at zero
+fn compute_potential_images(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    if field_configuration.image_conductors.is_empty() {
+        return 0.0;
+    }
+    field_configuration.with_image_charges(|images| {
+        images.induced.iter().chain(&images.net)
+            .map(|image| compute_potential_electrostatic_direct_one_charge(field_configuration, image, x, y)).sum::<f64>()
+            + images.potential_offset
+    })
 }
 
+/// The potential from everything other than the point charges: the uniform field, extended sources and dipoles
+fn compute_potential_electrostatic_external(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    let mut potential: f64 = (x-field_configuration.geometry.x_max/2.0) * field_configuration.elec_uniform_x + (y - field_configuration.geometry.y_max/2.0) * field_configuration.elec_uniform_y;
+    let epsilon = field_configuration.softening.length();
+    for source in &field_configuration.extended_sources {
+        potential -= field_configuration.field_scaling() * source.potential(x, y, epsilon, field_configuration.force_law);
+    }
+    for dipole in &field_configuration.dipoles {
+        potential -= field_configuration.field_scaling() * dipole.potential(x, y, epsilon, field_configuration.force_law);
+    }
+    potential
+}
+
+/// Total potential energy of the charges: their mutual interaction plus their energy in the external sources.
+///
+/// The force on a charge is $q\nabla\phi$ with this crate's sign convention for $\phi$, so the energy is $-q\phi$.
+/// The charges induced in image conductors count half, since the work of separating them is shared with the charges
+/// that induce them, as does the offset that grounds them, which depends on where the charges are. The total charge of
+/// an insulated image conductor counts in full.
 #[wasm_bindgen]
-pub fn compute_field_electrostatic_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
-    let mut u: f64 = field_configuration.elec_uniform_x;
-    let mut v: f64 = field_configuration.elec_uniform_y;
-    
+pub fn compute_potential_energy_electrostatic(field_config: &FieldConfiguration) -> f64 {
+    let mut energy = 0.0;
+    for (i, charge) in field_config.charges.iter().enumerate() {
+        energy -= charge.charge * compute_potential_electrostatic_external(field_config, charge.x, charge.y);
+        let outside_conductors = !field_config.in_image_conductor(charge.x, charge.y);
+        field_config.with_image_charges(|images| {
+            if outside_conductors {
+                energy -= 0.5 * charge.charge * images.potential_offset;
+            }
+            for image in &images.induced {
+                energy -= 0.5 * charge.charge * compute_potential_electrostatic_direct_one_charge(field_config, image, charge.x, charge.y);
+            }
+            for image in &images.net {
+                energy -= charge.charge * compute_potential_electrostatic_direct_one_charge(field_config, image, charge.x, charge.y);
+            }
+        });
+        for other_charge in &field_config.charges[i+1..] {
+            // symmetrise, in case the two charges have different softening
+            let phi_ij = compute_potential_electrostatic_direct_one_charge(field_config, other_charge, charge.x, charge.y);
+            let phi_ji = compute_potential_electrostatic_direct_one_charge(field_config, charge, other_charge.x, other_charge.y);
+            energy -= 0.5 * (charge.charge * phi_ij + other_charge.charge * phi_ji);
+        }
+    }
+    energy
+}
 
-    for charge in &field_configuration.charges {
-        let Pair { u: u_i, v: v_i } = compute_field_electrostatic_direct_one_charge(charge, x, y);
-        u += u_i;
-        v += v_i;
+fn compute_field_extended_sources(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    let epsilon = field_configuration.softening.length();
+    let mut u: f64 = 0.0;
+    let mut v: f64 = 0.0;
+    for source in &field_configuration.extended_sources {
+        let Pair { u: u_i, v: v_i } = source.field(x, y, epsilon, field_configuration.force_law);
+        u += field_configuration.field_scaling() * u_i;
+        v += field_configuration.field_scaling() * v_i;
     }
     Pair { u, v }
 }
 
-pub fn compute_force_electrostatic(field_config: &FieldConfiguration, for_charge: usize) -> Pair {
-    let charge = &field_config.charges[for_charge];
+/// The field of the point dipoles, optionally leaving out one of them
+fn compute_field_dipoles(field_configuration: &FieldConfiguration, x: f64, y: f64, exclude: Option<usize>) -> Pair {
+    let epsilon = field_configuration.softening.length();
     let mut u: f64 = 0.0;
     let mut v: f64 = 0.0;
-    for other_charge in &field_config.charges {
-        if other_charge as *const Charge == charge as *const Charge {
+    for (index, dipole) in field_configuration.dipoles.iter().enumerate() {
+        if Some(index) == exclude {
             continue;
         }
-        let Pair { u: u_i, v: v_i } = compute_field_electrostatic_direct_one_charge(other_charge, charge.x, charge.y);
-        u += u_i;
-        v += v_i;
+        let Pair { u: u_i, v: v_i } = dipole.field(x, y, epsilon, field_configuration.force_law);
+        u += field_configuration.field_scaling() * u_i;
+        v += field_configuration.field_scaling() * v_i;
     }
+    Pair { u, v }
+}
+
+fn compute_field_electrostatic_direct_one_charge(field_configuration: &FieldConfiguration, charge: &Charge, x: f64, y: f64) -> Pair {
+    let dx = x - charge.x;
+    let dy = y - charge.y;
+    let kernel = field_configuration.softening.for_charge(charge);
+    let k = field_configuration.field_scaling() * charge.charge * kernel.screened_field_factor(field_configuration.force_law, dx * dx + dy * dy, field_configuration.debye_length);
+    Pair { u: k * dx, v: k * dy }
+}
+
+/// The field of each charge at (x, y) together with that of its own images. The total charges of insulated image
+/// conductors belong to no charge and are left out, and there is no field inside an image conductor.
+fn compute_field_electrostatic_per_charge_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Vec<Pair> {
+    let inside = field_configuration.in_image_conductor(x, y);
+    field_configuration.charges.iter().map(|charge| {
+        if inside {
+            return Pair { u: 0.0, v: 0.0 };
+        }
+        let mut field = compute_field_electrostatic_direct_one_charge(field_configuration, charge, x, y);
+        for conductor in &field_configuration.image_conductors {
+            conductor.for_each_image(charge, field_configuration.force_law, |image| {
+                let Pair { u, v } = compute_field_electrostatic_direct_one_charge(field_configuration, image, x, y);
+                field.u += u;
+                field.v += v;
+            });
+        }
+        field
+    }).collect()
+}
+
+/// The field of the image charges in the image conductors, which every charge feels, including its own images
+fn compute_field_images(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    let mut field = Pair { u: 0.0, v: 0.0 };
+    if field_configuration.image_conductors.is_empty() {
+        return field;
+    }
+    field_configuration.with_image_charges(|images| {
+        for image in images.induced.iter().chain(&images.net) {
+            let Pair { u, v } = compute_field_electrostatic_direct_one_charge(field_configuration, image, x, y);
+            field.u += u;
+            field.v += v;
+        }
+    });
+    field
+}
+
+/// The field of all the point charges at (x, y), optionally leaving out one of them
+fn compute_field_charges_direct(field_configuration: &FieldConfiguration, x: f64, y: f64, exclude: Option<usize>) -> Pair {
+    let mut near = Pair { u: 0.0, v: 0.0 };
+    let mut far = Pair { u: 0.0, v: 0.0 };
+    field_configuration.for_each_charge_contribution(x, y, exclude, |charge| {
+        let Pair { u, v } = compute_field_electrostatic_direct_one_charge(field_configuration, charge, x, y);
+        near.u += u;
+        near.v += v;
+    }, |node| {
+        let Pair { u, v } = node.field(x, y, field_configuration.force_law);
+        far.u += field_configuration.field_scaling() * u;
+        far.v += field_configuration.field_scaling() * v;
+    });
+    Pair { u: near.u + far.u, v: near.v + far.v }
+}
+
+/// The field at (x, y) of the charges, extended sources, dipoles and uniform field, together with the images in any
+/// image conductors. There is no field inside an image conductor.
+#[wasm_bindgen]
+pub fn compute_field_electrostatic_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    if field_configuration.in_image_conductor(x, y) {
+        return Pair { u: 0.0, v: 0.0 };
+    }
+    compute_field_electrostatic_direct_excluding_dipole(field_configuration, x, y, None)
+}
+
+fn compute_field_electrostatic_direct_excluding_dipole(field_configuration: &FieldConfiguration, x: f64, y: f64, exclude: Option<usize>) -> Pair {
+    let Pair { u, v } = compute_field_sources_direct(field_configuration, x, y, None, exclude);
+    let Pair { u: u_img, v: v_img } = compute_field_images(field_configuration, x, y);
+    Pair { u: u + u_img + field_configuration.elec_uniform_x, v: v + v_img + field_configuration.elec_uniform_y }
+}
+
+/// The field of the charges, extended sources and dipoles at (x, y) without the uniform field, optionally leaving
+/// out one charge and one dipole
+fn compute_field_sources_direct(field_configuration: &FieldConfiguration, x: f64, y: f64, exclude_charge: Option<usize>, exclude_dipole: Option<usize>) -> Pair {
+    let Pair { u, v } = compute_field_charges_direct(field_configuration, x, y, exclude_charge);
+    let Pair { u: u_ext, v: v_ext } = compute_field_extended_sources(field_configuration, x, y);
+    let Pair { u: u_dip, v: v_dip } = compute_field_dipoles(field_configuration, x, y, exclude_dipole);
+    Pair { u: u + u_ext + u_dip, v: v + v_ext + v_dip }
+}
+
+/// The force on one charge from everything else, which is zero for a charge inside an image conductor
+pub fn compute_force_electrostatic(field_config: &FieldConfiguration, for_charge: usize) -> Pair {
+    let charge = &field_config.charges[for_charge];
+    if field_config.in_image_conductor(charge.x, charge.y) {
+        return Pair { u: 0.0, v: 0.0 };
+    }
+    let Pair { mut u, mut v } = compute_field_sources_direct(field_config, charge.x, charge.y, Some(for_charge), None);
+    // a charge is attracted by its own images as well as those of the others
+    let Pair { u: u_img, v: v_img } = compute_field_images(field_config, charge.x, charge.y);
+    u += u_img + field_config.elec_uniform_x;
+    v += v_img + field_config.elec_uniform_y;
     u*=charge.charge;
     v*=charge.charge;
     Pair { u, v }
@@ -479,27 +1277,226 @@ pub fn compute_forces_electrostatic(field_config: &FieldConfiguration) -> Vec<Pa
     forces
 }
 
+/// The potential at (x, y) of the charges and extended sources found by the spectral Poisson solver, together with the
+/// uniform field. With periodic boundaries the mean potential on the grid is zero, so the potential differs from
+/// compute_potential_electrostatic_direct by a constant as well as by the discretisation; with isolated boundaries
+/// it differs only by the discretisation.
+#[wasm_bindgen]
+pub fn compute_potential_electrostatic_fourier(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<f64, JsError> {
+    Ok(field_configuration.spectral_potential(x, y)?)
+}
+
+/// The field at (x, y) of the charges and extended sources found by the spectral Poisson solver, together with the
+/// uniform field
+#[wasm_bindgen]
+pub fn compute_field_electrostatic_fourier(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<Pair, JsError> {
+    Ok(field_configuration.spectral_field(x, y)?)
+}
+
+#[wasm_bindgen]
+pub fn compute_field_electrostatic_fourier_to_buffer(field_configuration: &FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) -> Result<(), JsError> {
+    let Pair { u, v } = field_configuration.spectral_field(x, y)?;
+    buffer[0] = u;
+    buffer[1] = v;
+    Ok(())
+}
+
+/// The force on each charge from the other charges, the extended sources and the uniform field, found by the spectral
+/// Poisson solver
+#[wasm_bindgen]
+pub fn compute_forces_electrostatic_fourier(field_config: &FieldConfiguration) -> Result<Vec<Pair>, JsError> {
+    Ok(field_config.spectral_forces()?)
+}
+
+/// The potential at (x, y) of the charges, extended sources and uniform field found by the multigrid solver, with the
+/// conductors, dielectrics and outer boundary condition
+#[wasm_bindgen]
+pub fn compute_potential_electrostatic_multigrid(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<f64, JsError> {
+    Ok(field_configuration.multigrid_potential(x, y)?)
+}
+
+/// The field at (x, y) found by the multigrid solver; see compute_potential_electrostatic_multigrid
+#[wasm_bindgen]
+pub fn compute_field_electrostatic_multigrid(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<Pair, JsError> {
+    Ok(field_configuration.multigrid_field(x, y)?)
+}
+
+#[wasm_bindgen]
+pub fn compute_field_electrostatic_multigrid_to_buffer(field_configuration: &FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) -> Result<(), JsError> {
+    let Pair { u, v } = field_configuration.multigrid_field(x, y)?;
+    buffer[0] = u;
+    buffer[1] = v;
+    Ok(())
+}
+
+/// The force on each charge found by the multigrid solver, including the attraction of the charges induced on any conductors
+#[wasm_bindgen]
+pub fn compute_forces_electrostatic_multigrid(field_config: &FieldConfiguration) -> Result<Vec<Pair>, JsError> {
+    Ok(field_config.multigrid_forces()?)
+}
+
+/// The potential and charge of each conductor in the multigrid solution, as an array in the order the conductors were
+/// set of `{potential, total, surface}`, where surface lists `{x, y, density}` with the charge per unit length around
+/// the conductor
+#[wasm_bindgen]
+pub fn compute_induced_charges_multigrid(field_config: &FieldConfiguration) -> Result<JsValue, JsError> {
+    Ok(to_value(&field_config.induced_charges()?)?)
+}
+
+/// The capacitance matrix of the conductors, as an array of rows in the order the conductors were set, found by the
+/// multigrid solver with each conductor in turn at unit potential and the others grounded
+#[wasm_bindgen]
+pub fn compute_capacitance_matrix_multigrid(field_config: &FieldConfiguration) -> Result<JsValue, JsError> {
+    Ok(to_value(&field_config.capacitance_matrix()?)?)
+}
+
+/// The in-plane magnetic field at (x, y) of the out-of-plane currents, current density and uniform field found by the
+/// multigrid magnetostatic solver, with the magnetic materials and outer boundary condition
+#[wasm_bindgen]
+pub fn compute_field_magnetostatic_multigrid(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<Pair, JsError> {
+    Ok(field_configuration.vector_potential_field(x, y)?)
+}
+
+#[wasm_bindgen]
+pub fn compute_field_magnetostatic_multigrid_to_buffer(field_configuration: &FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) -> Result<(), JsError> {
+    let Pair { u, v } = field_configuration.vector_potential_field(x, y)?;
+    buffer[0] = u;
+    buffer[1] = v;
+    Ok(())
+}
+
+/// The out-of-plane magnetic field $B_z$ at (x, y) of the in-plane current density, found by the multigrid
+/// magnetostatic solver
+#[wasm_bindgen]
+pub fn compute_field_z_magnetostatic_multigrid(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<f64, JsError> {
+    Ok(field_configuration.vector_potential_field_z(x, y)?)
+}
+
+/// The vector potential $A_z$ at (x, y) found by the multigrid magnetostatic solver, whose contours are field lines
+#[wasm_bindgen]
+pub fn compute_vector_potential_magnetostatic_multigrid(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Result<f64, JsError> {
+    Ok(field_configuration.vector_potential(x, y)?)
+}
+
+/// The force on each out-of-plane current found by the multigrid magnetostatic solver, including the pull of any
+/// magnetic materials
+#[wasm_bindgen]
+pub fn compute_forces_magnetostatic_multigrid(field_config: &FieldConfiguration) -> Result<Vec<Pair>, JsError> {
+    Ok(field_config.vector_potential_forces()?)
+}
+
+/// The potential at (x, y) of the point charges and all their periodic images, for a cell of size
+/// x_max by y_max, together with the uniform field.
+///
+/// The images are summed exactly by Ewald summation, for comparison with the implicitly periodic Fourier solver.
+/// A uniform background neutralises any net charge in the cell. Extended sources and dipoles are not included.
+#[wasm_bindgen]
+pub fn compute_potential_electrostatic_periodic(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    let mut potential: f64 = (x-field_configuration.geometry.x_max/2.0) * field_configuration.elec_uniform_x + (y - field_configuration.geometry.y_max/2.0) * field_configuration.elec_uniform_y;
+    field_configuration.with_ewald_sum(|ewald| {
+        for charge in &field_configuration.charges {
+            let kernel = field_configuration.softening.for_charge(charge);
+            potential -= field_configuration.field_scaling() * charge.charge * ewald.potential(x - charge.x, y - charge.y, &kernel);
+        }
+    });
+    potential
+}
+
+/// The field at (x, y) of the point charges and all their periodic images, together with the uniform field;
+/// see compute_potential_electrostatic_periodic
+#[wasm_bindgen]
+pub fn compute_field_electrostatic_periodic(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    field_configuration.with_ewald_sum(|ewald| compute_field_electrostatic_periodic_excluding_charge(field_configuration, ewald, x, y, None))
+}
+
+fn compute_field_electrostatic_periodic_excluding_charge(field_configuration: &FieldConfiguration, ewald: &ewald::EwaldSum, x: f64, y: f64, exclude: Option<usize>) -> Pair {
+    let mut u: f64 = field_configuration.elec_uniform_x;
+    let mut v: f64 = field_configuration.elec_uniform_y;
+    for (index, charge) in field_configuration.charges.iter().enumerate() {
+        if Some(index) == exclude {
+            // a charge's own images are arranged symmetrically about it, and exert no net force
+            continue;
+        }
+        let kernel = field_configuration.softening.for_charge(charge);
+        let Pair { u: u_i, v: v_i } = ewald.field(x - charge.x, y - charge.y, &kernel);
+        u += field_configuration.field_scaling() * charge.charge * u_i;
+        v += field_configuration.field_scaling() * charge.charge * v_i;
+    }
+    Pair { u, v }
+}
+
+/// The force on each charge from the other charges and all the periodic images; see
+/// compute_potential_electrostatic_periodic
+#[wasm_bindgen]
+pub fn compute_forces_electrostatic_periodic(field_config: &FieldConfiguration) -> Vec<Pair> {
+    field_config.with_ewald_sum(|ewald| field_config.charges.iter().enumerate().map(|(index, charge)| {
+        let Pair { u, v } = compute_field_electrostatic_periodic_excluding_charge(field_config, ewald, charge.x, charge.y, Some(index));
+        Pair { u: u * charge.charge, v: v * charge.charge }
+    }).collect())
+}
+
+/// The force on each point dipole from the uniform field, the charges and all other sources
+#[wasm_bindgen]
+pub fn compute_forces_on_dipoles_electrostatic(field_config: &FieldConfiguration) -> Vec<Pair> {
+    compute_forces_and_torques_on_dipoles(field_config).into_iter().map(|(force, _)| force).collect()
+}
+
+/// The torque on each point dipole (anticlockwise positive) from the uniform field, the charges and all other sources
+#[wasm_bindgen]
+pub fn compute_torques_on_dipoles_electrostatic(field_config: &FieldConfiguration) -> Vec<f64> {
+    compute_forces_and_torques_on_dipoles(field_config).into_iter().map(|(_, torque)| torque).collect()
+}
+
+fn compute_forces_and_torques_on_dipoles(field_config: &FieldConfiguration) -> Vec<(Pair, f64)> {
+    let epsilon = field_config.softening.length();
+    field_config.dipoles.iter().enumerate().map(|(index, dipole)| {
+        dipole.force_and_torque(|x, y| compute_field_electrostatic_direct_excluding_dipole(field_config, x, y, Some(index)), epsilon)
+    }).collect()
+}
+
 #[wasm_bindgen]
 pub fn compute_one_force_electrostatic(field_config: &FieldConfiguration, x: f64, y: f64, charge: f64) -> Pair {
     let Pair { u, v } = compute_field_electrostatic_direct(field_config, x, y);
     Pair { u: u*charge, v: v*charge }
 }
 
+/// The force on an out-of-plane current at (x, y), where charge is the current
 #[wasm_bindgen]
 pub fn compute_one_force_magnetostatic(field_config: &FieldConfiguration, x: f64, y: f64, charge: f64) -> Pair {
     let Pair { u, v } = compute_field_magnetostatic_direct(field_config, x, y);
+    let charge = field_config.units.current_force_factor() * charge;
     Pair { u: v*charge, v: -u*charge }
 }
 
+/// The force on each out-of-plane current from the other currents, the magnets and the uniform field
 #[wasm_bindgen]
 pub fn compute_forces_magnetostatic(field_config: &FieldConfiguration) -> Vec<Pair> {
-    let mut forces = compute_forces_electrostatic(field_config);
-    // negate the electric force components to get the magnetic force in the plane
-    for force in &mut forces {
-        force.u = -force.u;
-        force.v = -force.v;
-    }
-    forces
+    let factor = field_config.units.current_force_factor();
+    field_config.charges.iter().enumerate().map(|(index, charge)| {
+        // using the same convention as compute_one_force_magnetostatic
+        let Pair { u, v } = compute_field_magnetostatic_excluding(field_config, charge.x, charge.y, Some(index), None);
+        Pair { u: factor * v * charge.charge, v: -factor * u * charge.charge }
+    }).collect()
+}
+
+/// The force on each magnet from the currents and all other magnets
+#[wasm_bindgen]
+pub fn compute_forces_on_magnets_magnetostatic(field_config: &FieldConfiguration) -> Vec<Pair> {
+    compute_forces_and_torques_on_magnets(field_config).into_iter().map(|(force, _)| force).collect()
+}
+
+/// The torque on each magnet (anticlockwise positive, about its centre) from the currents and all other magnets
+#[wasm_bindgen]
+pub fn compute_torques_on_magnets_magnetostatic(field_config: &FieldConfiguration) -> Vec<f64> {
+    compute_forces_and_torques_on_magnets(field_config).into_iter().map(|(_, torque)| torque).collect()
+}
+
+fn compute_forces_and_torques_on_magnets(field_config: &FieldConfiguration) -> Vec<(Pair, f64)> {
+    let epsilon = field_config.softening.length();
+    field_config.magnets.iter().enumerate().map(|(index, magnet)| {
+        let b_external = |x: f64, y: f64| compute_field_magnetostatic_excluding(field_config, x, y, None, Some(index));
+        magnet.force_and_torque(b_external, epsilon)
+    }).collect()
 }
 
 #[wasm_bindgen]
@@ -512,8 +1509,7 @@ pub fn compute_field_electrostatic_direct_to_buffer(field_configuration: &FieldC
 #[wasm_bindgen]
 pub fn compute_field_electrostatic_per_charge_direct_to_buffer(field_configuration: &FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) {
     let mut offset = 0;
-    for charge in 0..field_configuration.charges.len() {
-        let Pair { u, v } = compute_field_electrostatic_direct_one_charge(&field_configuration.charges[charge], x, y);
+    for Pair { u, v } in compute_field_electrostatic_per_charge_direct(field_configuration, x, y) {
         buffer[offset] = u;
         buffer[offset+1] = v;
         offset += 2;
@@ -524,7 +1520,7 @@ pub fn compute_field_electrostatic_per_charge_direct_to_buffer(field_configurati
 pub fn compute_field_magnetostatic_per_charge_direct_to_buffer(field_configuration: &FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) {
     let mut offset = 0;
     for charge in 0..field_configuration.charges.len() {
-        let Pair { u, v } = compute_field_magnetostatic_direct_one_charge(&field_configuration.charges[charge], x, y);
+        let Pair { u, v } = compute_field_magnetostatic_direct_one_charge(field_configuration, &field_configuration.charges[charge], x, y);
         buffer[offset] = u;
         buffer[offset+1] = v;
         offset += 2;
@@ -535,16 +1531,83 @@ pub fn compute_field_magnetostatic_per_charge_direct_to_buffer(field_configurati
 
 #[wasm_bindgen]
 pub fn compute_field_magnetostatic_direct(field_configuration: &FieldConfiguration, x: f64, y:f64) -> Pair {
+    compute_field_magnetostatic_excluding(field_configuration, x, y, None, None)
+}
+
+/// The B field of the currents, magnets and uniform field, optionally leaving out one current and one magnet. Only the
+/// point charges stand for currents; the extended sources and electric dipoles are electrostatic and give no B field.
+fn compute_field_magnetostatic_excluding(field_configuration: &FieldConfiguration, x: f64, y: f64, exclude_charge: Option<usize>, exclude_magnet: Option<usize>) -> Pair {
     // Here we take advantage of the fact the Biot-Savart law in a plane looks like $\hat{z} \times \nabla \phi$
     // where $\phi$ is a fictional electrostatic potential generated by replacing currents with charges.
-    let Pair { u, v } = compute_field_electrostatic_direct(field_configuration, x, y);
-    Pair { u: v, v: -u }
+    let Pair { u, v } = compute_field_charges_direct(field_configuration, x, y, exclude_charge);
+    let factor = field_configuration.units.current_field_factor();
+    // the uniform field is rotated in the same way, but is already a magnetic field
+    let u = factor * u + field_configuration.elec_uniform_x;
+    let v = factor * v + field_configuration.elec_uniform_y;
+    let Pair { u: u_m, v: v_m } = compute_field_magnets_direct(field_configuration, x, y, exclude_magnet);
+    Pair { u: v + u_m, v: -u + v_m }
+}
+
+/// The B field of the permanent magnets, optionally leaving out one of them
+fn compute_field_magnets_direct(field_configuration: &FieldConfiguration, x: f64, y: f64, exclude: Option<usize>) -> Pair {
+    let epsilon = field_configuration.softening.length();
+    let mut u: f64 = 0.0;
+    let mut v: f64 = 0.0;
+    for (index, magnet) in field_configuration.magnets.iter().enumerate() {
+        if Some(index) == exclude {
+            continue;
+        }
+        let Pair { u: u_i, v: v_i } = magnet.b_field(x, y, epsilon, field_configuration.force_law);
+        u += field_configuration.units.magnet_scaling(field_configuration.force_law) * u_i;
+        v += field_configuration.units.magnet_scaling(field_configuration.force_law) * v_i;
+    }
+    Pair { u, v }
+}
+
+/// The H field of the permanent magnets, which is the gradient of compute_potential_magnetostatic_scalar_direct
+pub fn compute_field_magnetic_h_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> Pair {
+    let epsilon = field_configuration.softening.length();
+    let scaling = field_configuration.h_field_scaling();
+    let mut u: f64 = 0.0;
+    let mut v: f64 = 0.0;
+    for magnet in &field_configuration.magnets {
+        let Pair { u: u_i, v: v_i } = magnet.h_field(x, y, epsilon, field_configuration.force_law);
+        u += scaling * u_i;
+        v += scaling * v_i;
+    }
+    Pair { u, v }
 }
 
+/// The magnetic scalar potential of the permanent magnets, with the same sign convention as
+/// compute_potential_electrostatic_direct. Currents have no single-valued scalar potential and are not included.
 #[wasm_bindgen]
-pub fn compute_field_magnetostatic_direct_one_charge(charge: &Charge, x: f64, y: f64) -> Pair {
-    let Pair {u, v} = compute_field_electrostatic_direct_one_charge(charge, x, y);
-    Pair { u: v, v: -u }
+pub fn compute_potential_magnetostatic_scalar_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    let epsilon = field_configuration.softening.length();
+    field_configuration.magnets.iter().map(|magnet| -field_configuration.h_field_scaling() * magnet.scalar_potential(x, y, epsilon, field_configuration.force_law)).sum()
+}
+
+#[wasm_bindgen]
+pub fn compute_field_magnetostatic_direct_one_charge(field_configuration: &FieldConfiguration, charge: &Charge, x: f64, y: f64) -> Pair {
+    let Pair {u, v} = compute_field_electrostatic_direct_one_charge(field_configuration, charge, x, y);
+    let factor = field_configuration.units.current_field_factor();
+    Pair { u: factor * v, v: -factor * u }
+}
+
+/// The out-of-plane field $B_z$ generated by in-plane wires, via the Biot-Savart law
+#[wasm_bindgen]
+pub fn compute_field_magnetostatic_z_direct(field_configuration: &FieldConfiguration, x: f64, y: f64) -> f64 {
+    let epsilon = field_configuration.softening.length();
+    let scaling = field_configuration.wire_field_scaling();
+    field_configuration.wires.iter().map(|wire| scaling * wire.field_z(x, y, epsilon)).sum()
+}
+
+/// The in-plane force on each in-plane wire, due to the $B_z$ of the other wires
+#[wasm_bindgen]
+pub fn compute_forces_on_wires_magnetostatic(field_config: &FieldConfiguration) -> Vec<Pair> {
+    let epsilon = field_config.softening.length();
+    let scaling = field_config.wire_field_scaling() * field_config.units.current_force_factor();
+    wires::forces_on_wires(&field_config.wires, epsilon).into_iter()
+        .map(|Pair { u, v }| Pair { u: scaling * u, v: scaling * v }).collect()
 }
 
 #[wasm_bindgen]
@@ -555,14 +1618,17 @@ pub fn compute_field_magnetostatic_direct_to_buffer(field_configuration: &FieldC
 }
 
 #[wasm_bindgen]
-pub fn compute_electric_field_dynamic(field_configuration: &mut FieldConfiguration, x: f64, y: f64) -> Pair {
-    let (u, v) = field_configuration.evaluate_elec_interpolated(x, y);
-    Pair { u, v }
+pub fn compute_electric_field_dynamic(field_configuration: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, JsError> {
+    let (u, v) = field_configuration.evaluate_elec_interpolated(x, y)?;
+    Ok(Pair { u, v })
 }
 
 #[wasm_bindgen]
-pub fn compute_electric_field_dynamic_to_buffer(field_configuration: &mut FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) {
-    let Pair { u, v } = compute_electric_field_dynamic(field_configuration, x, y);
+pub fn compute_electric_field_dynamic_to_buffer(field_configuration: &mut FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) -> Result<(), JsError> {
+    let Pair { u, v } = compute_electric_field_dynamic(field_configuration, x, y)?;
     buffer[0] = u;
     buffer[1] = v;
+    Ok(())
 }
+
+
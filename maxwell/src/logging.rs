@@ -0,0 +1,176 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+
+/// The importance of a diagnostic message, in increasing order
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    #[default]
+    Warn,
+    Error,
+    /// As a threshold, suppresses every message
+    Off,
+}
+
+/// Receives the diagnostic messages that pass the current log level
+pub trait Logger {
+    fn log(&self, level: LogLevel, message: &str);
+}
+
+/// Writes to the browser console, at the console level matching the message
+struct ConsoleLogger;
+
+impl Logger for ConsoleLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        use web_sys::console;
+        let message = JsValue::from_str(message);
+        match level {
+            LogLevel::Debug => console::debug_1(&message),
+            LogLevel::Info => console::info_1(&message),
+            LogLevel::Warn => console::warn_1(&message),
+            _ => console::error_1(&message),
+        }
+    }
+}
+
+/// Writes to standard error, for use outside the browser where the console is unavailable
+struct StderrLogger;
+
+impl Logger for StderrLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        eprintln!("[maxwell {:?}] {}", level, message);
+    }
+}
+
+/// Passes each message to a JS function taking (level, message)
+struct JsLogger(js_sys::Function);
+
+impl Logger for JsLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        // an exception in the handler has nowhere better to go than being dropped
+        let _ = self.0.call2(&JsValue::NULL, &JsValue::from(level), &JsValue::from_str(message));
+    }
+}
+
+fn default_logger() -> Box<dyn Logger> {
+    if cfg!(target_arch = "wasm32") {
+        Box::new(ConsoleLogger)
+    } else {
+        Box::new(StderrLogger)
+    }
+}
+
+// wasm is single-threaded, so each thread keeps its own logger rather than requiring it to be Send
+thread_local! {
+    static LOGGER: RefCell<Box<dyn Logger>> = RefCell::new(default_logger());
+    static LOG_LEVEL: Cell<LogLevel> = Cell::new(LogLevel::default());
+}
+
+/// Replace the logger, e.g. to collect messages natively
+pub fn set_logger(logger: Box<dyn Logger>) {
+    LOGGER.with(|current| *current.borrow_mut() = logger);
+}
+
+/// Only pass on messages at this level or above; the default is Warn
+#[wasm_bindgen]
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.with(|current| current.set(level));
+}
+
+#[wasm_bindgen]
+pub fn get_log_level() -> LogLevel {
+    LOG_LEVEL.with(|current| current.get())
+}
+
+/// Send messages to a JS function called as `handler(level, message)`, or back to the console if handler is undefined
+#[wasm_bindgen]
+pub fn set_log_handler(handler: Option<js_sys::Function>) {
+    match handler {
+        Some(handler) => set_logger(Box::new(JsLogger(handler))),
+        None => set_logger(default_logger()),
+    }
+}
+
+pub fn log(level: LogLevel, message: fmt::Arguments) {
+    if level < get_log_level() || level == LogLevel::Off {
+        return;
+    }
+    LOGGER.with(|logger| logger.borrow().log(level, &message.to_string()));
+}
+
+pub fn debug(message: fmt::Arguments) {
+    log(LogLevel::Debug, message);
+}
+
+/// A recoverable problem met during a computation, which has carried on regardless
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Warning {
+    /// No point at the contour level could be found from (x, y), so the contour continues from there unchanged
+    CrossingPointNotFound { x: f64, y: f64, level: f64, steps: usize },
+    /// Bisection towards the contour level stopped at (x, y) before reaching the tolerance
+    BisectionNotConverged { x: f64, y: f64, level: f64, potential: f64, steps: usize },
+    /// Contouring stopped after the maximum number of contours, so some levels may be missing
+    TooManyContours { limit: usize },
+    /// A charge outside the grid was left out of the grid's charge density
+    ChargeOutsideGrid { x: f64, y: f64 },
+    /// The timestep is long enough that the dynamic solver may be inaccurate or unstable
+    LargeTimestep { courant_number: f64 },
+    /// The multigrid solver stopped with the residual still above its tolerance, relative to the starting residual
+    MultigridNotConverged { cycles: usize, residual: f64 },
+    /// The potentials of the isolated conductors could not be found from their charges, so they have been grounded
+    IsolatedPotentialsUndetermined { conductors: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::CrossingPointNotFound { x, y, level, steps } => {
+                write!(f, "no crossing point at level {} found from ({}, {}) after {} steps", level, x, y, steps)
+            },
+            Warning::BisectionNotConverged { x, y, level, potential, steps } => {
+                write!(f, "bisection towards level {} stopped at ({}, {}) with potential {} after {} steps", level, x, y, potential, steps)
+            },
+            Warning::TooManyContours { limit } => {
+                write!(f, "ran out of contours after {}", limit)
+            },
+            Warning::ChargeOutsideGrid { x, y } => {
+                write!(f, "charge at ({}, {}) is outside the grid and has been left out", x, y)
+            },
+            Warning::LargeTimestep { courant_number } => {
+                write!(f, "timestep is large compared to grid cell size: c dt/dx = {}", courant_number)
+            },
+            Warning::MultigridNotConverged { cycles, residual } => {
+                write!(f, "multigrid solver stopped after {} cycles with relative residual {}", cycles, residual)
+            },
+            Warning::IsolatedPotentialsUndetermined { conductors } => {
+                write!(f, "potentials of {} isolated conductors are undetermined and have been set to zero", conductors)
+            },
+        }
+    }
+}
+
+impl Warning {
+    /// Pass the warning to the logger without keeping it
+    pub fn report(&self) {
+        log(LogLevel::Warn, format_args!("{}", self));
+    }
+}
+
+/// The warnings raised by one computation, returned to JS alongside its results
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Warnings(Vec<Warning>);
+
+impl Warnings {
+    /// Record a warning, also passing it to the logger
+    pub fn push(&mut self, warning: Warning) {
+        warning.report();
+        self.0.push(warning);
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Serialize, Deserialize};
+use crate::Pair;
+use crate::sources::{ExtendedSource, polygon_edges, point_in_polygon, quadrature_along_segment};
+use crate::dipoles::{dipole_potential, dipole_field, dipole_force_and_torque};
+use crate::softening::ForceLaw;
+
+/// A permanent magnet, magnetised in the plane.
+///
+/// Magnets are described by their equivalent magnetic charge: a point dipole, or a surface charge $\vec{M}\cdot\hat{n}$
+/// on the boundary of a uniformly magnetised region. The $H$ field is then the gradient of a scalar potential, which
+/// can be contoured like the electrostatic potential.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Magnet {
+    /// Point dipole at (x, y) with moment (mx, my)
+    Dipole { x: f64, y: f64, mx: f64, my: f64 },
+    /// Uniformly magnetised polygon (e.g. a bar magnet) with magnetisation (mx, my) per unit area
+    Region { vertices: Vec<(f64, f64)>, mx: f64, my: f64 },
+}
+
+impl Magnet {
+    /// Equivalent magnetic line charges on the boundary of a magnetised region
+    fn boundary_charges(vertices: &[(f64, f64)], mx: f64, my: f64) -> Vec<ExtendedSource> {
+        let orientation = ExtendedSource::polygon_orientation(vertices);
+        polygon_edges(vertices).filter_map(|(a, b)| {
+            let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+            if length == 0.0 {
+                return None;
+            }
+            let (nx, ny) = (orientation * (b.1 - a.1) / length, -orientation * (b.0 - a.0) / length);
+            Some(ExtendedSource::Segment { x0: a.0, y0: a.1, x1: b.0, y1: b.1, line_density: mx * nx + my * ny })
+        }).collect()
+    }
+
+    /// The centre about which torques are measured
+    pub fn centre(&self) -> (f64, f64) {
+        match self {
+            Magnet::Dipole { x, y, .. } => (*x, *y),
+            Magnet::Region { vertices, .. } => {
+                let n = vertices.len().max(1) as f64;
+                let (sx, sy) = vertices.iter().fold((0.0, 0.0), |(sx, sy), v| (sx + v.0, sy + v.1));
+                (sx / n, sy / n)
+            }
+        }
+    }
+
+    /// The magnetic scalar potential $\psi$ at (x, y), without the overall field scaling, such that $H = -\nabla\psi$
+    pub fn scalar_potential(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> f64 {
+        match self {
+            Magnet::Dipole { x: xd, y: yd, mx, my } => dipole_potential(x - xd, y - yd, *mx, *my, epsilon, law),
+            Magnet::Region { vertices, mx, my } => {
+                Self::boundary_charges(vertices, *mx, *my).iter().map(|charge| charge.potential(x, y, epsilon, law)).sum()
+            }
+        }
+    }
+
+    /// The field $H$ at (x, y), without the overall field scaling
+    pub fn h_field(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> Pair {
+        match self {
+            Magnet::Dipole { x: xd, y: yd, mx, my } => dipole_field(x - xd, y - yd, *mx, *my, epsilon, law),
+            Magnet::Region { vertices, mx, my } => {
+                let mut result = Pair { u: 0.0, v: 0.0 };
+                for charge in Self::boundary_charges(vertices, *mx, *my) {
+                    let Pair { u, v } = charge.field(x, y, epsilon, law);
+                    result.u += u;
+                    result.v += v;
+                }
+                result
+            }
+        }
+    }
+
+    /// The field $B$ at (x, y), without the overall field scaling. This differs from $H$ only inside a magnetised
+    /// region, where $B = H + c M$ with $c$ the constant in Gauss's law (i.e. $4\pi$ for the inverse-square law).
+    pub fn b_field(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> Pair {
+        let Pair { mut u, mut v } = self.h_field(x, y, epsilon, law);
+        if let Magnet::Region { vertices, mx, my } = self {
+            if point_in_polygon(vertices, x, y) {
+                u += law.gauss_constant() * mx;
+                v += law.gauss_constant() * my;
+            }
+        }
+        Pair { u, v }
+    }
+
+    /// Force and torque (about the centre) on the magnet from an external field b_external.
+    ///
+    /// For a dipole, these are $\nabla(\vec{m}\cdot\vec{B})$ (by central differences) and $\vec{m}\times\vec{B}$;
+    /// for a region, the force and torque on its boundary magnetic charge are integrated along the edges.
+    pub fn force_and_torque(&self, b_external: impl Fn(f64, f64) -> Pair, epsilon: f64) -> (Pair, f64) {
+        match self {
+            Magnet::Dipole { x, y, mx, my } => dipole_force_and_torque(*x, *y, *mx, *my, b_external, epsilon),
+            Magnet::Region { vertices, mx, my } => {
+                let (cx, cy) = self.centre();
+                let mut force = Pair { u: 0.0, v: 0.0 };
+                let mut torque = 0.0;
+                for charge in Self::boundary_charges(vertices, *mx, *my) {
+                    if let ExtendedSource::Segment { x0, y0, x1, y1, line_density } = charge {
+                        let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+                        for (x, y, weight) in quadrature_along_segment((x0, y0), (x1, y1), epsilon.max(1.0)) {
+                            let Pair { u: bx, v: by } = b_external(x, y);
+                            let dq = line_density * length * weight;
+                            force.u += dq * bx;
+                            force.v += dq * by;
+                            torque += dq * ((x - cx) * by - (y - cy) * bx);
+                        }
+                    }
+                }
+                (force, torque)
+            }
+        }
+    }
+}
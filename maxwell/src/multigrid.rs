@@ -0,0 +1,910 @@
+use ndarray::Array2;
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+use crate::{Pair, FieldConfiguration};
+use crate::builder::ConfigurationError;
+use crate::error::MaxwellError;
+use crate::geometry::Geometry;
+use crate::grid_solution::GridSolution;
+use crate::logging::{self, Warning};
+use crate::softening::ForceLaw;
+use crate::sources::{point_in_polygon, polygon_edges};
+
+/// The condition on the potential at the outer edge of the grid, for the multigrid solver
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PotentialBoundary {
+    /// The potential at the edge is that of the uniform field alone, as if the grid were enclosed in a grounded box
+    #[default]
+    Dirichlet,
+    /// The normal field at the edge is that of the uniform field alone, so no other flux crosses it
+    Neumann,
+}
+
+/// A region of the plane, containing the cells whose centroids lie inside it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Region {
+    Disc { x: f64, y: f64, radius: f64 },
+    /// Filled polygon; vertices may be in either winding order
+    Polygon { vertices: Vec<(f64, f64)> },
+}
+
+impl Region {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            Region::Disc { x: x0, y: y0, radius } => (x - x0).powi(2) + (y - y0).powi(2) <= radius * radius,
+            Region::Polygon { vertices } => point_in_polygon(vertices, x, y),
+        }
+    }
+
+    /// Points around the edge of the region no further apart than spacing, ending where they started
+    pub fn outline(&self, spacing: f64) -> Vec<(f64, f64)> {
+        match self {
+            Region::Disc { x, y, radius } => {
+                let n = ((2.0 * std::f64::consts::PI * radius / spacing).ceil() as usize).max(8);
+                (0..=n).map(|k| {
+                    let angle = 2.0 * std::f64::consts::PI * (k % n) as f64 / n as f64;
+                    (x + radius * angle.cos(), y + radius * angle.sin())
+                }).collect()
+            },
+            Region::Polygon { vertices } => {
+                let mut outline = vec![];
+                for (a, b) in polygon_edges(vertices) {
+                    let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+                    let n = ((length / spacing).ceil() as usize).max(1);
+                    outline.extend((0..n).map(|k| {
+                        let t = k as f64 / n as f64;
+                        (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+                    }));
+                }
+                if let Some(&first) = outline.first() {
+                    outline.push(first);
+                }
+                outline
+            },
+        }
+    }
+}
+
+/// The index of the region covering the centroid of each cell of the grid, if any. Later regions take precedence
+/// where they overlap.
+pub(crate) fn rasterise<'a>(geometry: &Geometry, regions: impl IntoIterator<Item = &'a Region>) -> Array2<Option<usize>> {
+    let regions: Vec<&Region> = regions.into_iter().collect();
+    Array2::from_shape_fn((geometry.nx, geometry.ny), |(i, j)| {
+        let (x, y) = geometry.cell_to_centroid(i, j);
+        regions.iter().rposition(|region| region.contains(x, y))
+    })
+}
+
+/// A conductor held at a fixed potential, by a battery or by being grounded, or else isolated with a fixed total
+/// charge, which spreads over it to bring it all to the same potential
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Conductor {
+    pub region: Region,
+    /// Ignored for an isolated conductor, whose potential is solved for
+    #[serde(default)]
+    pub potential: f64,
+    /// The total charge on an isolated conductor, or None for one held at its potential
+    #[serde(default)]
+    pub charge: Option<f64>,
+}
+
+/// A region with a relative permittivity other than one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Dielectric {
+    pub region: Region,
+    pub permittivity: f64,
+}
+
+/// Axes with more cells than this are coarsened again
+const COARSEST_CELLS: usize = 3;
+const SMOOTHING_SWEEPS: usize = 2;
+const BOTTOM_SWEEPS: usize = 50;
+const MAX_CYCLES: usize = 100;
+/// The residual at which the solve stops, relative to that of the initial guess of zero
+const TOLERANCE: f64 = 1e-9;
+
+/// One grid of the multigrid hierarchy
+struct Level {
+    permittivity: Array2<f64>,
+    /// Cells whose potential is held fixed, or whose correction is held at zero on the coarser grids
+    fixed: Array2<bool>,
+    dx: f64,
+    dy: f64,
+}
+
+impl Level {
+    fn dim(&self) -> (usize, usize) {
+        self.permittivity.dim()
+    }
+
+    /// The coupling of cell (i, j) to each of its neighbours times their potential, summed, and the coupling summed
+    /// on its own. Across the outer edge, a Dirichlet condition couples the cell to a ghost value of zero half a cell
+    /// beyond it and a Neumann condition does not couple it at all; inhomogeneous conditions are moved to the sources.
+    fn neighbour_sum(&self, potential: &Array2<f64>, i: usize, j: usize, boundary: PotentialBoundary) -> (f64, f64) {
+        let (nx, ny) = self.dim();
+        let epsilon = self.permittivity[[i, j]];
+        let mut sum = 0.0;
+        let mut coupling = 0.0;
+        let neighbours = [(i.checked_sub(1), Some(j), self.dx), ((i + 1 < nx).then_some(i + 1), Some(j), self.dx),
+            (Some(i), j.checked_sub(1), self.dy), (Some(i), (j + 1 < ny).then_some(j + 1), self.dy)];
+        for (i_neighbour, j_neighbour, h) in neighbours {
+            match (i_neighbour, j_neighbour) {
+                (Some(i_neighbour), Some(j_neighbour)) => {
+                    let c = face_permittivity(epsilon, self.permittivity[[i_neighbour, j_neighbour]]) / (h * h);
+                    sum += c * potential[[i_neighbour, j_neighbour]];
+                    coupling += c;
+                },
+                _ => if boundary == PotentialBoundary::Dirichlet {
+                    coupling += 2.0 * epsilon / (h * h);
+                },
+            }
+        }
+        (sum, coupling)
+    }
+
+    /// Red-black Gauss-Seidel sweeps, leaving the fixed cells alone
+    fn smooth(&self, potential: &mut Array2<f64>, sources: &Array2<f64>, operator: &MultigridOperator, sweeps: usize) {
+        let (nx, ny) = self.dim();
+        for _ in 0..sweeps {
+            for colour in 0..2 {
+                for i in 0..nx {
+                    for j in ((i + colour) % 2..ny).step_by(2) {
+                        if self.fixed[[i, j]] {
+                            continue;
+                        }
+                        let (sum, coupling) = self.neighbour_sum(potential, i, j, operator.boundary);
+                        let diagonal = coupling + operator.screening;
+                        if diagonal > 0.0 {
+                            potential[[i, j]] = (sum - sources[[i, j]]) / diagonal;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn residual(&self, potential: &Array2<f64>, sources: &Array2<f64>, operator: &MultigridOperator) -> Array2<f64> {
+        Array2::from_shape_fn(self.dim(), |(i, j)| {
+            if self.fixed[[i, j]] {
+                0.0
+            } else {
+                let (sum, coupling) = self.neighbour_sum(potential, i, j, operator.boundary);
+                sources[[i, j]] - (sum - (coupling + operator.screening) * potential[[i, j]])
+            }
+        })
+    }
+
+    /// The next coarser grid, halving each axis that has more than COARSEST_CELLS cells
+    fn coarsen(&self) -> Option<Level> {
+        let (nx, ny) = self.dim();
+        let (coarsen_x, coarsen_y) = (nx > COARSEST_CELLS, ny > COARSEST_CELLS);
+        if !coarsen_x && !coarsen_y {
+            return None;
+        }
+        let coarse_nx = if coarsen_x { nx.div_ceil(2) } else { nx };
+        let coarse_ny = if coarsen_y { ny.div_ceil(2) } else { ny };
+        let mut permittivity = Array2::<f64>::zeros((coarse_nx, coarse_ny));
+        let mut fixed = Array2::<bool>::from_elem((coarse_nx, coarse_ny), false);
+        let mut counts = Array2::<f64>::zeros((coarse_nx, coarse_ny));
+        for i in 0..nx {
+            for j in 0..ny {
+                let coarse = (if coarsen_x { i / 2 } else { i }, if coarsen_y { j / 2 } else { j });
+                permittivity[coarse] += self.permittivity[[i, j]];
+                fixed[coarse] |= self.fixed[[i, j]];
+                counts[coarse] += 1.0;
+            }
+        }
+        permittivity /= &counts;
+        Some(Level {
+            permittivity, fixed,
+            dx: if coarsen_x { 2.0 * self.dx } else { self.dx },
+            dy: if coarsen_y { 2.0 * self.dy } else { self.dy },
+        })
+    }
+
+    /// Average the fine residual onto this (coarser) level
+    fn restrict(&self, fine: &Array2<f64>) -> Array2<f64> {
+        let (nx, ny) = self.dim();
+        let (coarsen_x, coarsen_y) = (fine.dim().0 != nx, fine.dim().1 != ny);
+        let mut result = Array2::<f64>::zeros((nx, ny));
+        let mut counts = Array2::<f64>::zeros((nx, ny));
+        for ((i, j), value) in fine.indexed_iter() {
+            let coarse = (if coarsen_x { i / 2 } else { i }, if coarsen_y { j / 2 } else { j });
+            result[coarse] += value;
+            counts[coarse] += 1.0;
+        }
+        result /= &counts;
+        result.zip_mut_with(&self.fixed, |r, &fixed| if fixed { *r = 0.0 });
+        result
+    }
+
+    /// Interpolate a correction on this (coarser) level bilinearly between cell centroids, and add it to the free
+    /// cells of the finer level
+    fn prolong_onto(&self, correction: &Array2<f64>, fine: &Level, potential: &mut Array2<f64>) {
+        let (nx, ny) = self.dim();
+        let (fine_nx, fine_ny) = fine.dim();
+        for i in 0..fine_nx {
+            let weights_x = prolongation_weights(i, fine_nx != nx, nx);
+            for j in 0..fine_ny {
+                if fine.fixed[[i, j]] {
+                    continue;
+                }
+                let weights_y = prolongation_weights(j, fine_ny != ny, ny);
+                let mut value = 0.0;
+                for (i_coarse, weight_x) in weights_x {
+                    for (j_coarse, weight_y) in weights_y {
+                        value += weight_x * weight_y * correction[[i_coarse, j_coarse]];
+                    }
+                }
+                potential[[i, j]] += value;
+            }
+        }
+    }
+}
+
+/// Permittivity between two cells, taking the harmonic mean so that the flux across an interface is continuous
+fn face_permittivity(a: f64, b: f64) -> f64 {
+    if a + b > 0.0 { 2.0 * a * b / (a + b) } else { 0.0 }
+}
+
+/// The coarse cells, and their weights, from which a fine cell is interpolated along one axis. A fine centroid lies a
+/// quarter of a coarse cell from its parent's centroid; at the edges the parent's value is used alone.
+fn prolongation_weights(i: usize, coarsened: bool, n_coarse: usize) -> [(usize, f64); 2] {
+    if !coarsened {
+        return [(i, 1.0), (i, 0.0)];
+    }
+    let parent = i / 2;
+    let neighbour = if i.is_multiple_of(2) { parent.checked_sub(1) } else { (parent + 1 < n_coarse).then_some(parent + 1) };
+    match neighbour {
+        Some(neighbour) => [(parent, 0.75), (neighbour, 0.25)],
+        None => [(parent, 1.0), (parent, 0.0)],
+    }
+}
+
+/// $\nabla\cdot(\epsilon\nabla\phi) - \phi/\lambda^2 = \rho$ discretised on a grid and its successively coarser
+/// versions, with the potential held fixed in some cells.
+///
+/// The permittivity lives at the cell centroids, and the five-point stencil takes the harmonic mean across each face.
+/// Each V-cycle smooths with red-black Gauss-Seidel, averages the residual onto the coarser grid and interpolates
+/// the correction back bilinearly.
+pub struct MultigridOperator {
+    levels: Vec<Level>,
+    boundary: PotentialBoundary,
+    /// $1/\lambda^2$, or zero for Poisson's equation
+    screening: f64,
+}
+
+impl MultigridOperator {
+    pub fn new(permittivity: Array2<f64>, fixed: Array2<bool>, dx: f64, dy: f64, boundary: PotentialBoundary, screening: f64) -> MultigridOperator {
+        let mut levels = vec![Level { permittivity, fixed, dx, dy }];
+        while let Some(coarser) = levels.last().unwrap().coarsen() {
+            levels.push(coarser);
+        }
+        MultigridOperator { levels, boundary, screening }
+    }
+
+    /// With Neumann boundaries, no screening and nothing held fixed, the potential is only defined up to a constant
+    /// and the sources must sum to zero
+    fn is_singular(&self) -> bool {
+        self.boundary == PotentialBoundary::Neumann && self.screening == 0.0 && !self.levels[0].fixed.iter().any(|&fixed| fixed)
+    }
+
+    /// Improve potential, whose fixed cells already hold their values, until it solves the equation with the given
+    /// sources. Returns a warning if the residual did not fall to the tolerance.
+    pub fn solve(&self, potential: &mut Array2<f64>, sources: &Array2<f64>) -> Option<Warning> {
+        let mut sources = sources.clone();
+        let singular = self.is_singular();
+        if singular {
+            // a net source has nowhere to go, so it is neutralised by a uniform background
+            remove_mean(&mut sources);
+        }
+
+        let top = &self.levels[0];
+        let mut initial = potential.clone();
+        initial.zip_mut_with(&top.fixed, |p, &fixed| if !fixed { *p = 0.0 });
+        let scale = max_abs(&top.residual(&initial, &sources, self));
+        let mut residual = max_abs(&top.residual(potential, &sources, self));
+
+        let mut cycles = 0;
+        while residual > TOLERANCE * scale && cycles < MAX_CYCLES {
+            self.v_cycle(0, potential, &sources, singular);
+            if singular {
+                remove_mean(potential);
+            }
+            residual = max_abs(&top.residual(potential, &sources, self));
+            cycles += 1;
+        }
+
+        logging::debug(format_args!("multigrid solve took {} cycles, with relative residual {}", cycles, residual / scale));
+        (residual > TOLERANCE * scale).then_some(Warning::MultigridNotConverged { cycles, residual: residual / scale })
+    }
+
+    fn v_cycle(&self, index: usize, potential: &mut Array2<f64>, sources: &Array2<f64>, singular: bool) {
+        let level = &self.levels[index];
+        let Some(coarser) = self.levels.get(index + 1) else {
+            level.smooth(potential, sources, self, BOTTOM_SWEEPS);
+            return;
+        };
+
+        level.smooth(potential, sources, self, SMOOTHING_SWEEPS);
+        let mut coarse_sources = coarser.restrict(&level.residual(potential, sources, self));
+        if singular {
+            remove_mean(&mut coarse_sources);
+        }
+        let mut correction = Array2::<f64>::zeros(coarser.dim());
+        self.v_cycle(index + 1, &mut correction, &coarse_sources, singular);
+        coarser.prolong_onto(&correction, level, potential);
+        level.smooth(potential, sources, self, SMOOTHING_SWEEPS);
+    }
+}
+
+fn max_abs(array: &Array2<f64>) -> f64 {
+    array.iter().fold(0.0, |max, x| max.max(x.abs()))
+}
+
+fn remove_mean(array: &mut Array2<f64>) {
+    let mean = array.mean().unwrap_or(0.0);
+    array.iter_mut().for_each(|x| *x -= mean);
+}
+
+/// The static potential and electric field of the charges, extended sources and uniform field, with conductors and
+/// dielectrics, found by solving $\nabla\cdot(\epsilon\nabla\phi) - \phi/\lambda^2 = \rho$ on the grid
+/// by multigrid.
+///
+/// The uniform field enters through the outer boundary condition, so that it is distorted by the regions inside. The
+/// field is the central difference of the potential, using ghost values beyond the edge from the boundary condition.
+///
+/// Isolated conductors are first solved for as if grounded. The problem is linear, so adding the potential of each
+/// isolated conductor raised to unit potential on its own, in the right proportions, brings every one of them to its
+/// given charge. Those unit solutions depend only on the regions, so they are kept for the next solve.
+pub struct MultigridSolution {
+    grid: GridSolution,
+    conductors: ConductorGrid,
+    /// The potential of each conductor, whether fixed or solved for
+    conductor_potentials: Vec<f64>,
+    /// The potential with each isolated conductor at unit potential and everything else at zero, in the order of
+    /// isolated_indices
+    isolated_responses: Vec<Array2<f64>>,
+    /// The charge on each isolated conductor (rows) for each of isolated_responses (columns)
+    isolated_capacitance: Vec<Vec<f64>>,
+}
+
+/// The charge on the surface of one conductor, induced by the charges, the uniform field, the other conductors and
+/// its own potential
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct InducedCharge {
+    /// The potential of the conductor, which for an isolated conductor is found by the solver
+    pub potential: f64,
+    /// The total charge on the conductor
+    pub total: f64,
+    /// The charge per unit length at the midpoint of each cell face between the conductor and the space outside
+    pub surface: Vec<SurfaceCharge>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SurfaceCharge {
+    pub x: f64,
+    pub y: f64,
+    pub density: f64,
+}
+
+impl MultigridSolution {
+    /// Solve for the configuration's sources, starting from a previous solution if there is one
+    pub fn new(config: &FieldConfiguration, previous: Option<MultigridSolution>) -> Result<MultigridSolution, MaxwellError> {
+        let conductors = ConductorGrid::new(config)?;
+        let geometry = &config.geometry;
+        let isolated = isolated_indices(&config.conductors);
+
+        // isolated conductors start out grounded
+        let mut conductor_potentials: Vec<f64> = config.conductors.iter().map(|conductor| conductor.potential).collect();
+        isolated.iter().for_each(|&index| conductor_potentials[index] = 0.0);
+
+        let (mut potential, isolated_responses, isolated_capacitance) = match previous {
+            Some(previous) => (previous.grid.into_potential_grid(), previous.isolated_responses, previous.isolated_capacitance),
+            None => {
+                let responses: Vec<Array2<f64>> = isolated.iter().map(|&index| conductors.unit_response(index)).collect();
+                let capacitance = isolated.iter().map(|&row| responses.iter().map(|response| {
+                    conductors.total_charges(response, geometry)[row]
+                }).collect()).collect();
+                (Array2::<f64>::zeros((geometry.nx, geometry.ny)), responses, capacitance)
+            },
+        };
+
+        let edge = EdgeCondition::new(config);
+        let mut sources = config.charge_density_on_grid();
+        edge.add_to_sources(&mut sources, &conductors.permittivity, geometry);
+        conductors.solve(&mut potential, &conductor_potentials, &sources);
+
+        if !isolated.is_empty() {
+            let grounded_charges = conductors.total_charges(&potential, geometry);
+            let missing_charges: Vec<f64> = isolated.iter().map(|&index| {
+                config.conductors[index].charge.unwrap_or(0.0) - grounded_charges[index]
+            }).collect();
+            let solved = if conductors.potentials_undetermined(isolated.len()) {
+                None
+            } else {
+                solve_linear(isolated_capacitance.clone(), missing_charges)
+            };
+            let isolated_potentials = solved.unwrap_or_else(|| {
+                Warning::IsolatedPotentialsUndetermined { conductors: isolated.len() }.report();
+                vec![0.0; isolated.len()]
+            });
+            for ((&index, response), &isolated_potential) in isolated.iter().zip(&isolated_responses).zip(&isolated_potentials) {
+                potential.scaled_add(isolated_potential, response);
+                conductor_potentials[index] = isolated_potential;
+            }
+        }
+
+        let (mut elec_x, mut elec_y) = edge.gradient(&potential, geometry);
+        // there is no field inside a conductor, however steeply the potential rises just outside it
+        for ((i, j), conductor) in conductors.cells.indexed_iter() {
+            if conductor.is_some() {
+                elec_x[[i, j]] = 0.0;
+                elec_y[[i, j]] = 0.0;
+            }
+        }
+
+        Ok(MultigridSolution { grid: GridSolution::new(&config.charges, potential, elec_x, elec_y), conductors, conductor_potentials,
+            isolated_responses, isolated_capacitance })
+    }
+
+    /// The potential of each conductor, in the order they were set
+    pub fn conductor_potentials(&self) -> &[f64] {
+        &self.conductor_potentials
+    }
+
+    /// The potential and charge of each conductor, in the order they were set
+    pub fn induced_charges(&self, geometry: &Geometry) -> Vec<InducedCharge> {
+        let mut charges: Vec<InducedCharge> = self.conductor_potentials.iter().map(|&potential| {
+            InducedCharge { potential, total: 0.0, surface: vec![] }
+        }).collect();
+        self.conductors.for_each_surface_face(self.grid.potential_grid(), geometry, |index, x, y, density, length| {
+            charges[index].total += density * length;
+            charges[index].surface.push(SurfaceCharge { x, y, density });
+        });
+        charges
+    }
+}
+
+/// The potential, including the uniform field, and the field on the grid
+impl AsRef<GridSolution> for MultigridSolution {
+    fn as_ref(&self) -> &GridSolution {
+        &self.grid
+    }
+}
+
+/// The indices of the isolated conductors
+fn isolated_indices(conductors: &[Conductor]) -> Vec<usize> {
+    conductors.iter().enumerate().filter(|(_, conductor)| conductor.charge.is_some()).map(|(index, _)| index).collect()
+}
+
+/// The conductors and dielectrics laid out on the grid, with the operator that solves for the potential around them
+struct ConductorGrid {
+    permittivity: Array2<f64>,
+    /// The index of the conductor covering each cell, if any
+    cells: Array2<Option<usize>>,
+    count: usize,
+    operator: MultigridOperator,
+    /// The charge deposited on the grid for a unit charge
+    grid_charge_scaling: f64,
+}
+
+impl ConductorGrid {
+    fn new(config: &FieldConfiguration) -> Result<ConductorGrid, MaxwellError> {
+        if config.force_law != ForceLaw::Logarithmic {
+            return Err(ConfigurationError::UnsupportedForceLaw { solver: config.solver, law: config.force_law }.into());
+        }
+        let geometry = &config.geometry;
+        let permittivity = rasterise(geometry, config.dielectrics.iter().map(|dielectric| &dielectric.region))
+            .map(|index| index.map_or(1.0, |index| config.dielectrics[index].permittivity));
+        let cells = rasterise(geometry, config.conductors.iter().map(|conductor| &conductor.region));
+
+        let fixed = cells.map(|conductor| conductor.is_some());
+        let screening = if config.debye_length > 0.0 { 1.0 / (config.debye_length * config.debye_length) } else { 0.0 };
+        let operator = MultigridOperator::new(permittivity.clone(), fixed, geometry.delta_x(), geometry.delta_y(), config.potential_boundary, screening);
+        Ok(ConductorGrid { permittivity, cells, count: config.conductors.len(), operator,
+            grid_charge_scaling: config.units.grid_charge_scaling(config.force_law) })
+    }
+
+    /// Whether, with this many of the conductors isolated, nothing sets the level of the potential. That happens with
+    /// Neumann edges and no screening when every conductor is isolated, leaving only the differences between their
+    /// potentials defined.
+    fn potentials_undetermined(&self, isolated: usize) -> bool {
+        self.operator.boundary == PotentialBoundary::Neumann && self.operator.screening == 0.0 && isolated == self.count
+    }
+
+    /// Solve for potential with each conductor at the given potential, reporting any failure to converge
+    fn solve(&self, potential: &mut Array2<f64>, conductor_potentials: &[f64], sources: &Array2<f64>) {
+        potential.zip_mut_with(&self.cells, |phi, conductor| if let Some(index) = conductor { *phi = conductor_potentials[*index] });
+        if let Some(warning) = self.operator.solve(potential, sources) {
+            warning.report();
+        }
+    }
+
+    /// The potential with one conductor at unit potential, the others grounded and no sources, charges or uniform field
+    fn unit_response(&self, index: usize) -> Array2<f64> {
+        let mut conductor_potentials = vec![0.0; self.count];
+        conductor_potentials[index] = 1.0;
+        let sources = Array2::<f64>::zeros(self.cells.dim());
+        let mut potential = sources.clone();
+        self.solve(&mut potential, &conductor_potentials, &sources);
+        potential
+    }
+
+    /// The total charge on each conductor for the given potential
+    fn total_charges(&self, potential: &Array2<f64>, geometry: &Geometry) -> Vec<f64> {
+        let mut totals = vec![0.0; self.count];
+        self.for_each_surface_face(potential, geometry, |index, _, _, density, length| totals[index] += density * length);
+        totals
+    }
+
+    /// Visit each face that a conductor shares with a cell outside it, passing the conductor's index, the midpoint
+    /// of the face, the charge per unit length there and the length of the face.
+    ///
+    /// The charge comes from Gauss's law applied to the flux of $\epsilon\vec{E}$ out through the face. Faces on
+    /// the outer edge of the grid are left out.
+    fn for_each_surface_face(&self, potential: &Array2<f64>, geometry: &Geometry, mut visit: impl FnMut(usize, f64, f64, f64, f64)) {
+        let (nx, ny) = potential.dim();
+        let (dx, dy) = (geometry.delta_x(), geometry.delta_y());
+        for ((i, j), conductor) in self.cells.indexed_iter() {
+            let Some(index) = *conductor else { continue };
+            let (x, y) = geometry.cell_to_centroid(i, j);
+            // each neighbour, with the offset to the face between them, the spacing across it and its length
+            let neighbours = [(i.checked_sub(1), Some(j), (-0.5 * dx, 0.0), dx, dy), ((i + 1 < nx).then_some(i + 1), Some(j), (0.5 * dx, 0.0), dx, dy),
+                (Some(i), j.checked_sub(1), (0.0, -0.5 * dy), dy, dx), (Some(i), (j + 1 < ny).then_some(j + 1), (0.0, 0.5 * dy), dy, dx)];
+            for (i_neighbour, j_neighbour, (x_offset, y_offset), h, length) in neighbours {
+                let (Some(i_neighbour), Some(j_neighbour)) = (i_neighbour, j_neighbour) else { continue };
+                if self.cells[[i_neighbour, j_neighbour]] == Some(index) {
+                    continue;
+                }
+                let epsilon = face_permittivity(self.permittivity[[i, j]], self.permittivity[[i_neighbour, j_neighbour]]);
+                let normal_field = (potential[[i_neighbour, j_neighbour]] - potential[[i, j]]) / h;
+                visit(index, x + x_offset, y + y_offset, epsilon * normal_field / self.grid_charge_scaling, length);
+            }
+        }
+    }
+}
+
+/// The capacitance matrix of the configuration's conductors, per unit length of the line conductors they describe.
+///
+/// Entry (j, k) is the charge on conductor j with conductor k at unit potential and the others grounded, found by one
+/// multigrid solve for each conductor. Isolated conductors are treated like the rest, and the charges and uniform
+/// field are left out. With this crate's sign convention for $\phi$, the charges are $Q = -C\phi$, so that the
+/// diagonal is positive as usual. The matrix is symmetric up to the error of the grid.
+pub fn capacitance_matrix(config: &FieldConfiguration) -> Result<Vec<Vec<f64>>, MaxwellError> {
+    let conductors = ConductorGrid::new(config)?;
+    let columns: Vec<Vec<f64>> = (0..conductors.count).map(|index| {
+        conductors.total_charges(&conductors.unit_response(index), &config.geometry)
+    }).collect();
+    Ok((0..conductors.count).map(|row| columns.iter().map(|column| -column[row]).collect()).collect())
+}
+
+/// Solve the small dense system matrix.x = rhs by Gaussian elimination with partial pivoting, or return None if the
+/// matrix is singular
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    let scale = matrix.iter().flatten().fold(0.0, |max: f64, x| max.max(x.abs()));
+    for column in 0..n {
+        let pivot = (column..n).max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() <= SINGULAR_PIVOT * scale {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+        let (upper, lower) = matrix.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            row.iter_mut().zip(pivot_row).skip(column).for_each(|(entry, pivot_entry)| *entry -= factor * pivot_entry);
+            rhs[column + 1 + offset] -= factor * rhs[column];
+        }
+    }
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// Pivots this small relative to the largest entry are taken to mean the matrix is singular
+const SINGULAR_PIVOT: f64 = 1e-9;
+
+/// The outer boundary condition, with the potential or normal field of the uniform field on each edge
+pub(crate) struct EdgeCondition {
+    boundary: PotentialBoundary,
+    uniform: Pair,
+    x_centre: f64,
+    y_centre: f64,
+}
+
+impl EdgeCondition {
+    pub(crate) fn new(config: &FieldConfiguration) -> EdgeCondition {
+        EdgeCondition { boundary: config.potential_boundary, uniform: config.get_uniform_field(),
+            x_centre: config.geometry.x_max / 2.0, y_centre: config.geometry.y_max / 2.0 }
+    }
+
+    /// The potential of the uniform field at (x, y), as in compute_potential_electrostatic_direct
+    fn uniform_potential(&self, x: f64, y: f64) -> f64 {
+        (x - self.x_centre) * self.uniform.u + (y - self.y_centre) * self.uniform.v
+    }
+
+    /// The uniform field's component along the outward normal of an edge
+    fn uniform_normal_field(&self, edge: Edge) -> f64 {
+        match edge {
+            Edge::Left => -self.uniform.u,
+            Edge::Right => self.uniform.u,
+            Edge::Bottom => -self.uniform.v,
+            Edge::Top => self.uniform.v,
+        }
+    }
+
+    /// The value at the centroid of the ghost cell beyond face, given the value phi in the cell inside it
+    fn ghost(&self, phi: f64, face: &EdgeFace) -> f64 {
+        match self.boundary {
+            PotentialBoundary::Dirichlet => 2.0 * self.uniform_potential(face.x, face.y) - phi,
+            PotentialBoundary::Neumann => phi + face.h * self.uniform_normal_field(face.edge),
+        }
+    }
+
+    /// Move the inhomogeneous part of the boundary condition, which the operator leaves out, into the sources
+    pub(crate) fn add_to_sources(&self, sources: &mut Array2<f64>, permittivity: &Array2<f64>, geometry: &Geometry) {
+        for face in edge_faces(geometry) {
+            let epsilon = permittivity[face.cell];
+            sources[face.cell] -= match self.boundary {
+                PotentialBoundary::Dirichlet => 2.0 * epsilon * self.uniform_potential(face.x, face.y) / (face.h * face.h),
+                PotentialBoundary::Neumann => epsilon * self.uniform_normal_field(face.edge) / face.h,
+            };
+        }
+    }
+
+    /// The central difference of the potential, reaching the ghost values at the edges
+    pub(crate) fn gradient(&self, potential: &Array2<f64>, geometry: &Geometry) -> (Array2<f64>, Array2<f64>) {
+        let (nx, ny) = potential.dim();
+        let (dx, dy) = (geometry.delta_x(), geometry.delta_y());
+        let mut low_x = Array2::<f64>::zeros((nx, ny));
+        let mut high_x = Array2::<f64>::zeros((nx, ny));
+        let mut low_y = Array2::<f64>::zeros((nx, ny));
+        let mut high_y = Array2::<f64>::zeros((nx, ny));
+        for i in 0..nx {
+            for j in 0..ny {
+                if i > 0 { low_x[[i, j]] = potential[[i - 1, j]]; }
+                if i + 1 < nx { high_x[[i, j]] = potential[[i + 1, j]]; }
+                if j > 0 { low_y[[i, j]] = potential[[i, j - 1]]; }
+                if j + 1 < ny { high_y[[i, j]] = potential[[i, j + 1]]; }
+            }
+        }
+        for face in edge_faces(geometry) {
+            let ghost = self.ghost(potential[face.cell], &face);
+            match face.edge {
+                Edge::Left => low_x[face.cell] = ghost,
+                Edge::Right => high_x[face.cell] = ghost,
+                Edge::Bottom => low_y[face.cell] = ghost,
+                Edge::Top => high_y[face.cell] = ghost,
+            }
+        }
+        ((high_x - low_x) / (2.0 * dx), (high_y - low_y) / (2.0 * dy))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+/// A face on the outer edge of the grid, centred on (x, y), between cell and the ghost cell a distance h beyond it
+struct EdgeFace {
+    cell: (usize, usize),
+    edge: Edge,
+    x: f64,
+    y: f64,
+    h: f64,
+}
+
+fn edge_faces(geometry: &Geometry) -> Vec<EdgeFace> {
+    let (nx, ny) = (geometry.nx, geometry.ny);
+    let (dx, dy) = (geometry.delta_x(), geometry.delta_y());
+    let mut faces = vec![];
+    for j in 0..ny {
+        let (x, y) = geometry.cell_to_centroid(0, j);
+        faces.push(EdgeFace { cell: (0, j), edge: Edge::Left, x: x - 0.5 * dx, y, h: dx });
+        let (x, y) = geometry.cell_to_centroid(nx - 1, j);
+        faces.push(EdgeFace { cell: (nx - 1, j), edge: Edge::Right, x: x + 0.5 * dx, y, h: dx });
+    }
+    for i in 0..nx {
+        let (x, y) = geometry.cell_to_centroid(i, 0);
+        faces.push(EdgeFace { cell: (i, 0), edge: Edge::Bottom, x, y: y - 0.5 * dy, h: dy });
+        let (x, y) = geometry.cell_to_centroid(i, ny - 1);
+        faces.push(EdgeFace { cell: (i, ny - 1), edge: Edge::Top, x, y: y + 0.5 * dy, h: dy });
+    }
+    faces
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+    use crate::Charge;
+    use crate::builder::{FieldConfigurationBuilder, SolverKind};
+    use crate::geometry::BoundaryCondition;
+    use crate::logging::{LogLevel, Logger};
+    use crate::softening::SofteningKernel;
+
+    /// Fields nearer than this to a charge are not compared, four softening lengths
+    const MIN_DISTANCE: f64 = 20.0;
+
+    /// Collects the messages logged during a test
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Logger for Recorder {
+        fn log(&self, _level: LogLevel, message: &str) {
+            self.0.borrow_mut().push(message.to_string());
+        }
+    }
+
+    /// Send the log to a list of messages for the rest of the test, which runs on a thread of its own
+    fn record_log() -> Rc<RefCell<Vec<String>>> {
+        let messages = Rc::new(RefCell::new(vec![]));
+        logging::set_logger(Box::new(Recorder(messages.clone())));
+        messages
+    }
+
+    /// A 500 by 500 grid of 128 by 128 cells with isolated edges for the spectral solver, and nothing in it
+    fn empty_configuration(boundary: PotentialBoundary) -> FieldConfiguration {
+        FieldConfigurationBuilder::new(500.0, 500.0, 128, 128)
+            .boundary_width(0)
+            .boundary_condition(BoundaryCondition::Isolated)
+            .solver(SolverKind::ElectrostaticMultigrid)
+            .force_law(ForceLaw::Logarithmic)
+            .potential_boundary(boundary)
+            .softening_kernel(SofteningKernel::Plummer { length: 5.0 })
+            .try_build()
+            .unwrap()
+    }
+
+    /// Four charges at cell centroids around the middle of the grid, with no net charge or dipole moment so that
+    /// the edges of the grid have little effect on the field inside
+    fn configuration(boundary: PotentialBoundary) -> FieldConfiguration {
+        let mut config = empty_configuration(boundary);
+        let cells = [(56, 64, 1.0), (72, 64, 1.0), (64, 56, -1.0), (64, 72, -1.0)];
+        config.charges = cells.iter().map(|&(i, j, charge)| {
+            let (x, y) = config.geometry.cell_to_centroid(i, j);
+            Charge::new(x, y, charge)
+        }).collect();
+        config.charges_changed();
+        config
+    }
+
+    /// Check the multigrid field against the spectral field of open space over the middle half of the grid, away
+    /// from the charges, to within a fraction of the largest field compared
+    fn assert_matches_isolated_spectral(boundary: PotentialBoundary, tolerance: f64) {
+        let messages = record_log();
+        let config = configuration(boundary);
+        let mut largest_field: f64 = 0.0;
+        let mut largest_error: f64 = 0.0;
+        for i in 32..96 {
+            for j in 32..96 {
+                let (x, y) = config.geometry.cell_to_centroid(i, j);
+                if config.charges.iter().any(|charge| (x - charge.x).hypot(y - charge.y) < MIN_DISTANCE) {
+                    continue;
+                }
+                let multigrid = config.multigrid_field(x, y).unwrap();
+                let spectral = config.spectral_field(x, y).unwrap();
+                largest_field = largest_field.max(spectral.u.hypot(spectral.v));
+                largest_error = largest_error.max((multigrid.u - spectral.u).hypot(multigrid.v - spectral.v));
+            }
+        }
+        assert!(messages.borrow().is_empty(), "{:?}", messages.borrow());
+        assert!(largest_field > 0.0);
+        assert!(largest_error < tolerance * largest_field, "{:?}: multigrid field differs by {} from a largest field of {}", boundary, largest_error, largest_field);
+    }
+
+    #[test]
+    fn dirichlet_field_matches_isolated_spectral() {
+        assert_matches_isolated_spectral(PotentialBoundary::Dirichlet, 0.02);
+    }
+
+    #[test]
+    fn neumann_field_matches_isolated_spectral() {
+        assert_matches_isolated_spectral(PotentialBoundary::Neumann, 0.02);
+    }
+
+    // A dielectric cylinder of relative permittivity $\epsilon$ in a uniform field $E_0$ has the uniform field
+    // $2 E_0 / (1 + \epsilon)$ inside it, which the grid finds to a few percent with the stepped edge of the disc
+    #[test]
+    fn dielectric_cylinder_in_uniform_field_converges() {
+        let messages = record_log();
+        let mut config = empty_configuration(PotentialBoundary::Dirichlet);
+        let permittivity = 4.0;
+        let radius = 50.0;
+        config.set_dielectrics_vec(vec![Dielectric { region: Region::Disc { x: 250.0, y: 250.0, radius }, permittivity }]);
+        config.set_uniform_field(1.0, 0.0);
+        let expected = 2.0 / (1.0 + permittivity);
+        for (x, y) in [(250.0, 250.0), (230.0, 240.0), (265.0, 270.0)] {
+            let Pair { u, v } = config.multigrid_field(x, y).unwrap();
+            assert!((u - expected).abs() < 0.05 * expected && v.abs() < 0.05 * expected, "field ({}, {}) at ({}, {}), expected ({}, 0)", u, v, x, y, expected);
+        }
+        assert!(messages.borrow().is_empty(), "{:?}", messages.borrow());
+    }
+
+    /// A single charge of 1 beside an isolated disc with the given total charge
+    fn charge_beside_isolated_disc(boundary: PotentialBoundary, charge: f64) -> FieldConfiguration {
+        let mut config = empty_configuration(boundary);
+        config.charges = vec![Charge::new(330.0, 250.0, 1.0)];
+        config.charges_changed();
+        config.set_conductors_vec(vec![Conductor { region: Region::Disc { x: 230.0, y: 250.0, radius: 40.0 }, potential: 0.0, charge: Some(charge) }]);
+        config
+    }
+
+    #[test]
+    fn neutral_isolated_conductor_stays_neutral() {
+        let config = charge_beside_isolated_disc(PotentialBoundary::Dirichlet, 0.0);
+        let induced = config.induced_charges().unwrap();
+        // the charge still separates over the surface, leaving the near side negative
+        assert!(induced[0].surface.iter().any(|surface| surface.density.abs() > 1e-3));
+        assert!(induced[0].total.abs() < 1e-6, "total charge {}", induced[0].total);
+    }
+
+    #[test]
+    fn isolated_conductor_carries_its_charge() {
+        let config = charge_beside_isolated_disc(PotentialBoundary::Dirichlet, 2.5);
+        let induced = config.induced_charges().unwrap();
+        assert!((induced[0].total - 2.5).abs() < 1e-6, "total charge {}", induced[0].total);
+        assert_eq!(config.conductor_potentials().unwrap()[0], induced[0].potential);
+    }
+
+    // With Neumann edges and every conductor isolated, nothing fixes the level of the potential
+    #[test]
+    fn undetermined_isolated_potentials_are_reported() {
+        let messages = record_log();
+        let config = charge_beside_isolated_disc(PotentialBoundary::Neumann, 0.0);
+        assert_eq!(config.conductor_potentials().unwrap(), vec![0.0]);
+        let expected = Warning::IsolatedPotentialsUndetermined { conductors: 1 }.to_string();
+        assert_eq!(*messages.borrow(), vec![expected]);
+    }
+
+    #[test]
+    fn capacitance_matrix_is_symmetric_with_the_usual_signs() {
+        let mut config = empty_configuration(PotentialBoundary::Dirichlet);
+        config.set_conductors_vec(vec![
+            Conductor { region: Region::Disc { x: 180.0, y: 250.0, radius: 30.0 }, potential: 0.0, charge: None },
+            Conductor { region: Region::Disc { x: 320.0, y: 230.0, radius: 45.0 }, potential: 0.0, charge: None },
+            Conductor { region: Region::Polygon { vertices: vec![(220.0, 340.0), (300.0, 340.0), (300.0, 370.0), (220.0, 370.0)] }, potential: 0.0, charge: Some(1.0) },
+        ]);
+        let capacitance = capacitance_matrix(&config).unwrap();
+        for (j, row) in capacitance.iter().enumerate() {
+            assert!(row[j] > 0.0, "diagonal entry {} is {}", j, row[j]);
+            for (k, &entry) in row.iter().enumerate().filter(|&(k, _)| k != j) {
+                assert!(entry < 0.0, "entry ({}, {}) is {}", j, k, entry);
+                assert!((entry - capacitance[k][j]).abs() < 0.01 * entry.abs(), "entries ({}, {}) and ({}, {}) differ", j, k, k, j);
+            }
+        }
+    }
+
+    // The capacitance per unit length of a coaxial line is $2\pi\epsilon_0 / \ln(b/a)$, which is
+    // $1 / (f \ln(b/a))$ with f the field scaling of the logarithmic law. The cells held at the inner conductor's
+    // potential all lie within radius a, which makes it a little smaller and the capacitance a few percent low.
+    #[test]
+    fn coaxial_capacitance_matches_analytic() {
+        let mut config = empty_configuration(PotentialBoundary::Dirichlet);
+        let (inner, outer) = (40.0, 150.0);
+        // the outer conductor fills the grid outside radius b, reached by a slit along y = 250 that holds no cells
+        let mut vertices = vec![(0.0, 250.0), (0.0, 0.0), (500.0, 0.0), (500.0, 500.0), (0.0, 500.0), (0.0, 250.0)];
+        vertices.extend(Region::Disc { x: 250.0, y: 250.0, radius: outer }.outline(1.0).into_iter().map(|(x, y)| (500.0 - x, y)));
+        config.set_conductors_vec(vec![
+            Conductor { region: Region::Disc { x: 250.0, y: 250.0, radius: inner }, potential: 0.0, charge: None },
+            Conductor { region: Region::Polygon { vertices }, potential: 0.0, charge: None },
+        ]);
+        let capacitance = capacitance_matrix(&config).unwrap();
+        let expected = 1.0 / (config.units.field_scaling(ForceLaw::Logarithmic) * (outer / inner).ln());
+        assert!((capacitance[0][0] - expected).abs() < 0.05 * expected, "capacitance {}, expected {}", capacitance[0][0], expected);
+        assert!((capacitance[0][1] + expected).abs() < 0.05 * expected, "mutual capacitance {}, expected {}", capacitance[0][1], -expected);
+    }
+}
@@ -0,0 +1,243 @@
+use wasm_bindgen::prelude::*;
+use crate::{Pair, FieldConfiguration, compute_forces_electrostatic, compute_potential_energy_electrostatic};
+use crate::error::MaxwellError;
+
+/// Energies of an N-body system after a step
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct EnergyReport {
+    pub kinetic: f64,
+    pub potential: f64,
+    pub total: f64,
+}
+
+/// N-body dynamics of the point charges under the direct electrostatic forces.
+///
+/// Uses the kick-drift-kick leapfrog, which is symplectic so that in the absence of damping the total energy
+/// is conserved to within a bounded error. Pinned charges feel forces but never move.
+#[wasm_bindgen]
+pub struct ElectrostaticNBody {
+    velocities: Vec<(f64, f64)>,
+    masses: Vec<f64>,
+    pinned: Vec<bool>,
+    /// Fractional loss of velocity per unit time, e.g. to let a system settle into a crystal
+    damping: f64,
+}
+
+impl Default for ElectrostaticNBody {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl ElectrostaticNBody {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ElectrostaticNBody {
+        ElectrostaticNBody { velocities: vec![], masses: vec![], pinned: vec![], damping: 0.0 }
+    }
+
+    pub fn set_damping(&mut self, damping: f64) {
+        self.damping = damping;
+    }
+
+    /// Set all the masses at once, throwing if any is not positive; charges beyond the end of the list have unit mass
+    pub fn set_masses(&mut self, masses: Vec<f64>) -> Result<(), JsError> {
+        Ok(self.try_set_masses(masses)?)
+    }
+
+    /// Set the mass of one charge, throwing if it is not positive
+    pub fn set_mass(&mut self, index: usize, mass: f64) -> Result<(), JsError> {
+        Ok(self.try_set_mass(index, mass)?)
+    }
+
+    pub fn set_pinned(&mut self, index: usize, pinned: bool) {
+        self.grow(index + 1);
+        self.pinned[index] = pinned;
+        if pinned {
+            self.velocities[index] = (0.0, 0.0);
+        }
+    }
+
+    pub fn set_velocity(&mut self, index: usize, vx: f64, vy: f64) {
+        self.grow(index + 1);
+        self.velocities[index] = (vx, vy);
+    }
+
+    pub fn velocity(&self, index: usize) -> Pair {
+        let (u, v) = self.velocities.get(index).copied().unwrap_or((0.0, 0.0));
+        Pair { u, v }
+    }
+
+    /// The kinetic energy of every charge that has been given a velocity, as of the last step
+    pub fn kinetic_energy(&self) -> f64 {
+        self.kinetic_energy_of_first(self.velocities.len())
+    }
+
+    /// Advance the charges in field_config by dt, returning the energies at the end of the step
+    pub fn step(&mut self, field_config: &mut FieldConfiguration, dt: f64) -> EnergyReport {
+        let n = field_config.charges.len();
+        self.resize(n);
+
+        // the forces are found afresh at the start of every step, as the configuration may have been changed in
+        // any way since the last one
+        let forces = compute_forces_electrostatic(field_config);
+        self.kick(&forces, 0.5 * dt);
+
+        for i in 0..n {
+            if !self.pinned[i] {
+                field_config.charges[i].x += self.velocities[i].0 * dt;
+                field_config.charges[i].y += self.velocities[i].1 * dt;
+            }
+        }
+        field_config.charges_changed();
+
+        let forces = compute_forces_electrostatic(field_config);
+        self.kick(&forces, 0.5 * dt);
+
+        if self.damping > 0.0 {
+            let damping_factor = (-self.damping * dt).exp();
+            for velocity in &mut self.velocities {
+                velocity.0 *= damping_factor;
+                velocity.1 *= damping_factor;
+            }
+        }
+
+        let kinetic = self.kinetic_energy_of_first(n);
+        let potential = compute_potential_energy_electrostatic(field_config);
+        EnergyReport { kinetic, potential, total: kinetic + potential }
+    }
+}
+
+impl ElectrostaticNBody {
+    /// Set all the masses at once, as for set_masses
+    pub fn try_set_masses(&mut self, masses: Vec<f64>) -> Result<(), MaxwellError> {
+        if let Some(&mass) = masses.iter().find(|&&mass| !is_valid_mass(mass)) {
+            return Err(MaxwellError::InvalidMass { mass });
+        }
+        self.masses = masses;
+        Ok(())
+    }
+
+    /// Set the mass of one charge, as for set_mass
+    pub fn try_set_mass(&mut self, index: usize, mass: f64) -> Result<(), MaxwellError> {
+        if !is_valid_mass(mass) {
+            return Err(MaxwellError::InvalidMass { mass });
+        }
+        self.grow(index + 1);
+        self.masses[index] = mass;
+        Ok(())
+    }
+
+    /// Make sure there is a velocity, mass and pinned flag for each of n charges, and none for charges that have
+    /// since been removed
+    fn resize(&mut self, n: usize) {
+        self.velocities.resize(n, (0.0, 0.0));
+        self.masses.resize(n, 1.0);
+        self.pinned.resize(n, false);
+    }
+
+    /// Make sure there is a velocity, mass and pinned flag for each of at least n charges, ahead of the next step
+    fn grow(&mut self, n: usize) {
+        self.resize(n.max(self.velocities.len()).max(self.masses.len()).max(self.pinned.len()));
+    }
+
+    /// The kinetic energy of the first n charges, taking those without a mass to have unit mass
+    fn kinetic_energy_of_first(&self, n: usize) -> f64 {
+        self.velocities.iter().take(n).enumerate()
+            .map(|(i, (vx, vy))| 0.5 * self.masses.get(i).copied().unwrap_or(1.0) * (vx * vx + vy * vy)).sum()
+    }
+
+    fn kick(&mut self, forces: &[Pair], dt: f64) {
+        for (i, force) in forces.iter().enumerate() {
+            if !self.pinned[i] {
+                self.velocities[i].0 += force.u / self.masses[i] * dt;
+                self.velocities[i].1 += force.v / self.masses[i] * dt;
+            }
+        }
+    }
+}
+
+fn is_valid_mass(mass: f64) -> bool {
+    mass.is_finite() && mass > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Charge;
+    use crate::builder::{FieldConfigurationBuilder, SolverKind};
+    use crate::softening::ForceLaw;
+
+    /// Four charges that push one another apart
+    fn configuration(law: ForceLaw) -> FieldConfiguration {
+        let mut config = FieldConfigurationBuilder::new(500.0, 500.0, 100, 100)
+            .solver(SolverKind::ElectrostaticDirect)
+            .force_law(law)
+            .try_build()
+            .unwrap();
+        config.charges = vec![Charge::new(200.0, 200.0, 1.0), Charge::new(300.0, 210.0, 1.0), Charge::new(260.0, 300.0, 1.0),
+            Charge::new(250.0, 240.0, 0.5)];
+        config.charges_changed();
+        config
+    }
+
+    /// Check that the total energy stays within a small fraction of the largest kinetic energy reached, over steps
+    /// in which the charges fly well apart
+    fn assert_energy_conserved(law: ForceLaw) {
+        let mut config = configuration(law);
+        let mut nbody = ElectrostaticNBody::new();
+        nbody.set_velocity(0, 3.0, 2.0);
+        nbody.set_velocity(2, 0.0, -4.0);
+        let initial = compute_potential_energy_electrostatic(&config) + nbody.kinetic_energy();
+        let mut largest_kinetic: f64 = 0.0;
+        let mut largest_error: f64 = 0.0;
+        for _ in 0..2000 {
+            let report = nbody.step(&mut config, 0.01);
+            largest_kinetic = largest_kinetic.max(report.kinetic);
+            largest_error = largest_error.max((report.total - initial).abs());
+        }
+        assert!(largest_kinetic > 100.0);
+        assert!(largest_error < 1e-4 * largest_kinetic, "{:?}: energy drifted by {} with kinetic energy up to {}", law, largest_error, largest_kinetic);
+    }
+
+    #[test]
+    fn inverse_square_energy_is_conserved() {
+        assert_energy_conserved(ForceLaw::InverseSquare);
+    }
+
+    #[test]
+    fn logarithmic_energy_is_conserved() {
+        assert_energy_conserved(ForceLaw::Logarithmic);
+    }
+
+    #[test]
+    fn pinned_charges_stay_put() {
+        let mut config = configuration(ForceLaw::InverseSquare);
+        let start = config.charges.clone();
+        let mut nbody = ElectrostaticNBody::new();
+        nbody.set_velocity(1, 5.0, 5.0);
+        nbody.set_pinned(1, true);
+        nbody.set_pinned(3, true);
+        for _ in 0..100 {
+            nbody.step(&mut config, 0.01);
+        }
+        for index in [1, 3] {
+            assert_eq!(config.charges[index], start[index]);
+            let Pair { u, v } = nbody.velocity(index);
+            assert_eq!((u, v), (0.0, 0.0));
+        }
+        assert_ne!(config.charges[0], start[0]);
+        assert_ne!(config.charges[2], start[2]);
+    }
+
+    #[test]
+    fn masses_must_be_positive_and_finite() {
+        let mut nbody = ElectrostaticNBody::new();
+        nbody.try_set_masses(vec![2.0, 3.0]).unwrap();
+        assert!(matches!(nbody.try_set_masses(vec![1.0, f64::NAN]), Err(MaxwellError::InvalidMass { .. })));
+        assert!(matches!(nbody.try_set_mass(0, 0.0), Err(MaxwellError::InvalidMass { mass }) if mass == 0.0));
+        assert!(matches!(nbody.try_set_mass(1, -1.0), Err(MaxwellError::InvalidMass { .. })));
+        assert_eq!(nbody.masses, vec![2.0, 3.0]);
+    }
+}
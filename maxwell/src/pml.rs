@@ -1,11 +1,21 @@
 use crate::Geometry;
+use crate::geometry::BoundaryWidths;
 
 pub struct PmlIterator {
     i: usize,
     j: usize,
     nx: usize,
     ny: usize,
-    layer_width: usize,
+    layer_widths: BoundaryWidths,
+}
+
+/// The damping in a layer of the given width, which rises linearly from zero at its inner edge
+fn damping(distance_to_boundary: usize, layer_width: usize) -> f64 {
+    if distance_to_boundary < layer_width {
+        1.0 - distance_to_boundary as f64 / layer_width as f64
+    } else {
+        0.0
+    }
 }
 
 impl Iterator for PmlIterator {
@@ -20,18 +30,13 @@ impl Iterator for PmlIterator {
                 return None;
             }
         }
-        let sigma_x = if self.i < self.layer_width || self.i >= self.nx - self.layer_width { 
-            let distance_to_boundary = self.i.min(self.nx - self.i - 1) as f64;
-            1.0 - distance_to_boundary / self.layer_width as f64 
-        } else { 0.0 };
-        let sigma_y = if self.j < self.layer_width || self.j >= self.ny - self.layer_width { 
-            let distance_to_boundary = self.j.min(self.ny - self.j - 1) as f64;
-            1.0 - distance_to_boundary / self.layer_width as f64 
-        } else { 0.0 };
+        let widths = &self.layer_widths;
+        let sigma_x = damping(self.i, widths.x_low).max(damping(self.nx - self.i - 1, widths.x_high));
+        let sigma_y = damping(self.j, widths.y_low).max(damping(self.ny - self.j - 1, widths.y_high));
         Some((self.i, self.j, sigma_x, sigma_y))
     }
 }
 
 pub fn pml_iterator_from_geometry(geometry: &Geometry) -> PmlIterator {
-    PmlIterator { i: 0, j: 0, nx: geometry.nx, ny: geometry.ny, layer_width: geometry.nboundary }
+    PmlIterator { i: 0, j: 0, nx: geometry.nx, ny: geometry.ny, layer_widths: geometry.boundary }
 }
\ No newline at end of file
@@ -0,0 +1,424 @@
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+use crate::Charge;
+
+/// The shape of a finite-sized charge of a given radius
+///
+/// As with the softening kernels, each profile is the cross-section through a spherically-symmetric distribution
+/// in the plane, so that a disc represents a uniformly charged sphere and a ring a charged spherical shell.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargeProfile {
+    /// A point charge, softened by the global kernel
+    #[default]
+    Point,
+    /// Uniformly charged disc (sphere)
+    Disc,
+    /// Charge spread over the circumference (spherical shell), with no field inside
+    Ring,
+    /// Gaussian blob whose standard deviation is the radius
+    Gaussian,
+}
+
+/// The law of interaction between charges in the plane
+///
+/// Charges may either be points in three dimensions, which happen to lie in the plane, or the cross-sections of
+/// infinitely long line charges perpendicular to it. The grid solvers are genuinely two-dimensional, and so agree
+/// with the direct solvers only for the logarithmic law unless told otherwise.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ForceLaw {
+    /// Point charges, with potential $1/r$ and field $\vec{r}/r^3$
+    #[default]
+    InverseSquare,
+    /// Line charges, with potential $-\ln r$ and field $\vec{r}/r^2$
+    Logarithmic,
+}
+
+impl ForceLaw {
+    /// The constant $c$ in Gauss's law $\nabla\cdot\vec{E} = c\rho$ for the unit charge of this law
+    pub fn gauss_constant(&self) -> f64 {
+        match self {
+            ForceLaw::InverseSquare => 4.0 * std::f64::consts::PI,
+            ForceLaw::Logarithmic => 2.0 * std::f64::consts::PI,
+        }
+    }
+}
+
+/// The profile used to smooth point sources, shared by the direct solvers and by deposition onto the grid.
+///
+/// For the inverse-square law, each kernel describes a spherically-symmetric charge distribution. The direct
+/// solvers use its potential and field in the plane, while grid deposition uses its surface density projected
+/// along z, so that both solvers see the same source. For the logarithmic law, the kernel instead describes the
+/// cross-section of a line charge: the Plummer and Gaussian profiles are the same in the plane as before, while
+/// the top hat becomes a uniform disc and the shell a ring.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SofteningKernel {
+    /// No softening at all; the field diverges at the location of the charge
+    #[serde(alias = "none")]
+    Point,
+    /// Plummer sphere, i.e. the potential $1/\sqrt{r^2+\epsilon^2}$
+    Plummer { length: f64 },
+    /// Gaussian ball with the given standard deviation
+    Gaussian { width: f64 },
+    /// Uniformly charged ball, whose cross-section in the plane is a disc of the given radius
+    TopHat { radius: f64 },
+    /// Charged spherical shell, whose cross-section in the plane is a ring of the given radius
+    Shell { radius: f64 },
+    /// Plummer softening with the length taken from the radius of each charge
+    PerCharge,
+}
+
+impl Default for SofteningKernel {
+    fn default() -> Self {
+        // Matches the historical softening of the direct solvers, $r^2 \to r^2 + 5$
+        SofteningKernel::Plummer { length: 5.0_f64.sqrt() }
+    }
+}
+
+impl SofteningKernel {
+    /// Resolve the kernel to be used for a specific charge
+    ///
+    /// Finite-sized charges carry their own profile, which takes precedence over the global kernel
+    pub fn for_charge(&self, charge: &Charge) -> SofteningKernel {
+        if charge.radius > 0.0 {
+            match charge.profile {
+                ChargeProfile::Disc => return SofteningKernel::TopHat { radius: charge.radius },
+                ChargeProfile::Ring => return SofteningKernel::Shell { radius: charge.radius },
+                ChargeProfile::Gaussian => return SofteningKernel::Gaussian { width: charge.radius },
+                ChargeProfile::Point => (),
+            }
+        }
+        match self {
+            SofteningKernel::PerCharge if charge.radius > 0.0 => SofteningKernel::Plummer { length: charge.radius },
+            SofteningKernel::PerCharge => SofteningKernel::Point,
+            _ => *self,
+        }
+    }
+
+    /// The characteristic length of the kernel (zero for a point)
+    pub fn length(&self) -> f64 {
+        match self {
+            SofteningKernel::Point | SofteningKernel::PerCharge => 0.0,
+            SofteningKernel::Plummer { length } => *length,
+            SofteningKernel::Gaussian { width } => *width,
+            SofteningKernel::TopHat { radius } | SofteningKernel::Shell { radius } => *radius,
+        }
+    }
+
+    /// Return a kernel of the same shape whose length is at least min_length.
+    ///
+    /// The grid cannot represent structure smaller than a couple of cells, so this is used before deposition.
+    /// A point is broadened to a Gaussian, which was the historical grid softening.
+    pub fn with_minimum_length(&self, min_length: f64) -> SofteningKernel {
+        if self.length() >= min_length {
+            return *self;
+        }
+        match self {
+            SofteningKernel::Point | SofteningKernel::PerCharge => SofteningKernel::Gaussian { width: min_length },
+            SofteningKernel::Plummer { .. } => SofteningKernel::Plummer { length: min_length },
+            SofteningKernel::Gaussian { .. } => SofteningKernel::Gaussian { width: min_length },
+            SofteningKernel::TopHat { .. } => SofteningKernel::TopHat { radius: min_length },
+            SofteningKernel::Shell { .. } => SofteningKernel::Shell { radius: min_length },
+        }
+    }
+
+    /// Factor $g(r)$ such that the field of a unit charge is $g(r) \vec{r}$
+    pub fn field_factor(&self, law: ForceLaw, r2: f64) -> f64 {
+        match law {
+            ForceLaw::InverseSquare => self.field_factor_inverse_square(r2),
+            ForceLaw::Logarithmic => self.field_factor_logarithmic(r2),
+        }
+    }
+
+    /// Factor $h(r)$ such that the potential of a unit charge is $-h(r)$
+    pub fn potential_factor(&self, law: ForceLaw, r2: f64) -> f64 {
+        match law {
+            ForceLaw::InverseSquare => self.potential_factor_inverse_square(r2),
+            ForceLaw::Logarithmic => self.potential_factor_logarithmic(r2),
+        }
+    }
+
+    fn field_factor_inverse_square(&self, r2: f64) -> f64 {
+        match self.for_radius_zero() {
+            SofteningKernel::Plummer { length } => {
+                let s2 = r2 + length * length;
+                1.0 / (s2 * s2.sqrt())
+            },
+            SofteningKernel::Gaussian { width } => {
+                let r = r2.sqrt();
+                let x = r / (std::f64::consts::SQRT_2 * width);
+                if x < 0.5 {
+                    gaussian_enclosed_fraction_over_cube(x) / (2.0 * std::f64::consts::SQRT_2 * width * width * width)
+                } else {
+                    let enclosed = erf(x) - 2.0 / std::f64::consts::PI.sqrt() * x * (-x * x).exp();
+                    enclosed / (r2 * r)
+                }
+            },
+            SofteningKernel::TopHat { radius } if r2 < radius * radius => 1.0 / (radius * radius * radius),
+            SofteningKernel::Shell { radius } if r2 < radius * radius => 0.0,
+            _ => {
+                if r2 == 0.0 {
+                    0.0
+                } else {
+                    1.0 / (r2 * r2.sqrt())
+                }
+            },
+        }
+    }
+
+    fn potential_factor_inverse_square(&self, r2: f64) -> f64 {
+        match self.for_radius_zero() {
+            SofteningKernel::Plummer { length } => 1.0 / (r2 + length * length).sqrt(),
+            SofteningKernel::Gaussian { width } => {
+                let r = r2.sqrt();
+                let x = r / (std::f64::consts::SQRT_2 * width);
+                if x < 0.5 {
+                    // series expansion of erf(x)/x, which is more accurate than the approximation to erf
+                    let mut sum = 0.0;
+                    let mut term = 1.0;
+                    for n in 0..12 {
+                        sum += term / (2 * n + 1) as f64;
+                        term *= -x * x / (n + 1) as f64;
+                    }
+                    2.0 / std::f64::consts::PI.sqrt() * sum / (std::f64::consts::SQRT_2 * width)
+                } else {
+                    erf(x) / r
+                }
+            },
+            SofteningKernel::TopHat { radius } if r2 < radius * radius => (3.0 * radius * radius - r2) / (2.0 * radius * radius * radius),
+            SofteningKernel::Shell { radius } if r2 < radius * radius => 1.0 / radius,
+            _ => 1.0 / r2.sqrt(),
+        }
+    }
+
+    fn field_factor_logarithmic(&self, r2: f64) -> f64 {
+        match self.for_radius_zero() {
+            SofteningKernel::Plummer { length } => 1.0 / (r2 + length * length),
+            SofteningKernel::Gaussian { width } => {
+                let x = r2 / (2.0 * width * width);
+                if x < 1e-8 {
+                    1.0 / (2.0 * width * width)
+                } else {
+                    -(-x).exp_m1() / r2
+                }
+            },
+            SofteningKernel::TopHat { radius } if r2 < radius * radius => 1.0 / (radius * radius),
+            SofteningKernel::Shell { radius } if r2 < radius * radius => 0.0,
+            _ => if r2 == 0.0 { 0.0 } else { 1.0 / r2 },
+        }
+    }
+
+    fn potential_factor_logarithmic(&self, r2: f64) -> f64 {
+        match self.for_radius_zero() {
+            SofteningKernel::Plummer { length } => -0.5 * (r2 + length * length).ln(),
+            SofteningKernel::Gaussian { width } => -0.5 * log_plus_exponential_integral(r2, 2.0 * width * width),
+            SofteningKernel::TopHat { radius } if r2 < radius * radius => 0.5 * (1.0 - r2 / (radius * radius)) - radius.ln(),
+            SofteningKernel::Shell { radius } if r2 < radius * radius => -radius.ln(),
+            _ => if r2 == 0.0 { 0.0 } else { -0.5 * r2.ln() },
+        }
+    }
+
+    /// Factor $g(r)$ for the field of a unit charge screened with the given Debye length (zero for no screening).
+    ///
+    /// A screened point charge has the Yukawa potential $e^{-r/\lambda}/r$ for the inverse-square law, and
+    /// $K_0(r/\lambda)$ for the logarithmic law. For a softened charge, the difference between the screened and
+    /// unscreened point potentials is evaluated at $\sqrt{r^2+\epsilon^2}$ and added to the softened potential. This
+    /// is exact for the Plummer kernel, and otherwise correct to order $(\epsilon/\lambda)^2$.
+    pub fn screened_field_factor(&self, law: ForceLaw, r2: f64, debye_length: f64) -> f64 {
+        let unscreened = self.field_factor(law, r2);
+        if debye_length <= 0.0 {
+            return unscreened;
+        }
+        let length = self.length();
+        unscreened + screening_field_correction(law, r2 + length * length, debye_length)
+    }
+
+    /// Factor $h(r)$ for the potential of a unit screened charge; see screened_field_factor.
+    ///
+    /// The screened potential vanishes at infinity, including for the logarithmic law.
+    pub fn screened_potential_factor(&self, law: ForceLaw, r2: f64, debye_length: f64) -> f64 {
+        let unscreened = self.potential_factor(law, r2);
+        if debye_length <= 0.0 {
+            return unscreened;
+        }
+        let length = self.length();
+        unscreened + screening_potential_correction(law, r2 + length * length, debye_length)
+    }
+
+    /// Unnormalised surface density of the kernel in the plane, at squared distance r2
+    ///
+    /// For the inverse-square law this is the spherical profile projected along z.
+    pub fn surface_density(&self, law: ForceLaw, r2: f64) -> f64 {
+        match (self.for_radius_zero(), law) {
+            (SofteningKernel::Plummer { length }, _) => {
+                let u = 1.0 + r2 / (length * length);
+                1.0 / (u * u)
+            },
+            (SofteningKernel::Gaussian { width }, _) => (-r2 / (2.0 * width * width)).exp(),
+            (SofteningKernel::TopHat { radius }, ForceLaw::InverseSquare) => (1.0 - r2 / (radius * radius)).max(0.0).sqrt(),
+            (SofteningKernel::TopHat { radius }, ForceLaw::Logarithmic) => if r2 < radius * radius { 1.0 } else { 0.0 },
+            (SofteningKernel::Shell { radius }, ForceLaw::InverseSquare) => {
+                // The projected shell has an integrable divergence at its rim, which is capped to keep the
+                // deposited profile finite on the grid
+                if r2 < radius * radius {
+                    1.0 / (1.0 - r2 / (radius * radius)).max(0.01).sqrt()
+                } else {
+                    0.0
+                }
+            },
+            (SofteningKernel::Shell { radius }, ForceLaw::Logarithmic) => {
+                // A ring cannot be represented on the grid, so deposit a narrow annulus inside it instead
+                if r2 < radius * radius && r2 > 0.64 * radius * radius { 1.0 } else { 0.0 }
+            },
+            _ => if r2 == 0.0 { 1.0 } else { 0.0 },
+        }
+    }
+
+    /// Distance beyond which the surface density may be neglected
+    pub fn extent(&self) -> f64 {
+        match self.for_radius_zero() {
+            SofteningKernel::Plummer { length } => 8.0 * length,
+            SofteningKernel::Gaussian { width } => 4.0 * width,
+            SofteningKernel::TopHat { radius } | SofteningKernel::Shell { radius } => radius,
+            _ => 0.0,
+        }
+    }
+
+    /// Kernels with zero length (or unresolved per-charge kernels) behave as points
+    fn for_radius_zero(&self) -> SofteningKernel {
+        if self.length() > 0.0 {
+            *self
+        } else {
+            SofteningKernel::Point
+        }
+    }
+}
+
+/// The screened minus the unscreened point potential factor, at squared distance s2. This is finite at s = 0.
+fn screening_potential_correction(law: ForceLaw, s2: f64, debye_length: f64) -> f64 {
+    let s = s2.sqrt();
+    let x = s / debye_length;
+    match law {
+        ForceLaw::InverseSquare => if s == 0.0 { -1.0 / debye_length } else { (-x).exp_m1() / s },
+        ForceLaw::Logarithmic => {
+            if x <= 2.0 {
+                // $K_0(x) + \ln s$, with the logarithms from the series for $K_0$ cancelled analytically
+                let u = 0.25 * x * x;
+                let i0_minus_one = bessel_i0(x) - 1.0;
+                let log_term = if x > 0.0 { -(0.5 * x).ln() * i0_minus_one } else { 0.0 };
+                log_term + std::f64::consts::LN_2 + debye_length.ln() + bessel_k0_series_remainder(u)
+            } else {
+                bessel_k0(x) + s.ln()
+            }
+        },
+    }
+}
+
+/// The screened minus the unscreened point field factor, at squared distance s2
+fn screening_field_correction(law: ForceLaw, s2: f64, debye_length: f64) -> f64 {
+    if s2 == 0.0 {
+        return 0.0;
+    }
+    let s = s2.sqrt();
+    let x = s / debye_length;
+    match law {
+        // $(e^{-x}(1+x) - 1)/s^3$
+        ForceLaw::InverseSquare => ((-x).exp_m1() * (1.0 + x) + x) / (s2 * s),
+        ForceLaw::Logarithmic => {
+            if x <= 2.0 {
+                // $(x K_1(x) - 1)/s^2$, with the leading 1 of the series for $x K_1(x)$ cancelled analytically
+                let u = 0.25 * x * x;
+                let remainder = u * (0.15443144 + u * (-0.67278579 + u * (-0.18156897 + u * (-0.01919402 + u * (-0.00110404 + u * -0.00004686)))));
+                ((0.5 * x).ln() * bessel_i1_over_x(x) + remainder / (x * x)) / (debye_length * debye_length)
+            } else {
+                (x * bessel_k1(x) - 1.0) / s2
+            }
+        },
+    }
+}
+
+/// Modified Bessel function $I_0(x)$ for $|x| \leq 3.75$, using 9.8.1 of Abramowitz & Stegun
+fn bessel_i0(x: f64) -> f64 {
+    let t2 = (x / 3.75).powi(2);
+    1.0 + t2 * (3.5156229 + t2 * (3.0899424 + t2 * (1.2067492 + t2 * (0.2659732 + t2 * (0.0360768 + t2 * 0.0045813)))))
+}
+
+/// $I_1(x)/x$ for $|x| \leq 3.75$, using 9.8.3 of Abramowitz & Stegun
+fn bessel_i1_over_x(x: f64) -> f64 {
+    let t2 = (x / 3.75).powi(2);
+    0.5 + t2 * (0.87890594 + t2 * (0.51498869 + t2 * (0.15084934 + t2 * (0.02658733 + t2 * (0.00301532 + t2 * 0.00032411)))))
+}
+
+/// The polynomial part of 9.8.5 of Abramowitz & Stegun, $K_0(x) + \ln(x/2) I_0(x)$ for $x \leq 2$, in terms of
+/// $u = x^2/4$
+fn bessel_k0_series_remainder(u: f64) -> f64 {
+    -0.57721566 + u * (0.42278420 + u * (0.23069756 + u * (0.03488590 + u * (0.00262698 + u * (0.00010750 + u * 0.00000740)))))
+}
+
+/// Modified Bessel function $K_0(x)$ for $x \geq 2$, using 9.8.6 of Abramowitz & Stegun (relative error below 2e-7)
+fn bessel_k0(x: f64) -> f64 {
+    let t = 2.0 / x;
+    let poly = 1.25331414 + t * (-0.07832358 + t * (0.02189568 + t * (-0.01062446 + t * (0.00587872 + t * (-0.00251540 + t * 0.00053208)))));
+    poly * (-x).exp() / x.sqrt()
+}
+
+/// Modified Bessel function $K_1(x)$ for $x \geq 2$, using 9.8.8 of Abramowitz & Stegun (relative error below 2.2e-7)
+fn bessel_k1(x: f64) -> f64 {
+    let t = 2.0 / x;
+    let poly = 1.25331414 + t * (0.23498619 + t * (-0.03655620 + t * (0.01504268 + t * (-0.00780353 + t * (0.00325614 + t * -0.00068245)))));
+    poly * (-x).exp() / x.sqrt()
+}
+
+/// The series $\sum_{n\geq 1} (-1)^{n+1} x^{2n-2} 2n / (n! (2n+1))$, equal to
+/// $(\textrm{erf}(x) - 2x e^{-x^2}/\sqrt{\pi}) \sqrt{\pi} / (2x^3)$, which cancels badly when evaluated directly at small x
+fn gaussian_enclosed_fraction_over_cube(x: f64) -> f64 {
+    let x2 = x * x;
+    let mut sum = 0.0;
+    let mut power = 1.0;
+    let mut factorial = 1.0;
+    for n in 1..12 {
+        factorial *= n as f64;
+        sum += power * (2 * n) as f64 / (factorial * (2 * n + 1) as f64);
+        power *= -x2;
+    }
+    2.0 / std::f64::consts::PI.sqrt() * sum
+}
+
+/// $\ln r^2 + E_1(r^2/s^2)$, which stays finite as r tends to zero
+pub fn log_plus_exponential_integral(r2: f64, s2: f64) -> f64 {
+    const EULER_GAMMA: f64 = 0.5772156649015329;
+    let x = r2 / s2;
+    if x <= 1.0 {
+        // E_1(x) = -\gamma - \ln x - \sum_k (-x)^k / (k k!)
+        let mut sum = 0.0;
+        let mut term = 1.0;
+        for k in 1..20 {
+            term *= -x / k as f64;
+            sum += term / k as f64;
+        }
+        s2.ln() - EULER_GAMMA - sum
+    } else {
+        r2.ln() + exponential_integral(x)
+    }
+}
+
+/// Exponential integral $E_1(x)$ for $x \geq 1$, using the approximation 5.1.56 of Abramowitz & Stegun
+/// (relative error below 5e-8)
+fn exponential_integral(x: f64) -> f64 {
+    let numerator = x * (x * (x * (x + 8.5733287401) + 18.0590169730) + 8.6347608925) + 0.2677737343;
+    let denominator = x * (x * (x * (x + 9.5733223454) + 25.6329561486) + 21.0996530827) + 3.9584969228;
+    numerator / denominator * (-x).exp() / x
+}
+
+/// Error function, using the approximation 7.1.26 of Abramowitz & Stegun (absolute error below 1.5e-7)
+pub fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
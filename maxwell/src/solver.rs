@@ -0,0 +1,262 @@
+use wasm_bindgen::prelude::*;
+use crate::{Pair, FieldConfiguration};
+use crate::builder::SolverKind;
+use crate::error::MaxwellError;
+
+/// Something a solver may or may not be able to compute
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverCapability {
+    /// The field at any point
+    Field,
+    /// A scalar potential whose gradient is the field
+    Potential,
+    /// The force on each charge (or current) from everything else
+    Forces,
+    /// The field of each charge (or current) separately
+    PerCharge,
+}
+
+/// A method of finding the fields of a FieldConfiguration.
+///
+/// The field is E for the electrostatic solvers and B for the magnetostatic solvers, in which charges stand for
+/// out-of-plane currents. Methods not covered by supports() return MaxwellError::Unsupported.
+pub trait FieldSolver {
+    fn kind(&self) -> SolverKind;
+
+    fn supports(&self, capability: SolverCapability) -> bool;
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError>;
+
+    fn potential(&self, _config: &mut FieldConfiguration, _x: f64, _y: f64) -> Result<f64, MaxwellError> {
+        Err(MaxwellError::Unsupported { solver: self.kind(), capability: SolverCapability::Potential })
+    }
+
+    fn forces(&self, _config: &mut FieldConfiguration) -> Result<Vec<Pair>, MaxwellError> {
+        Err(MaxwellError::Unsupported { solver: self.kind(), capability: SolverCapability::Forces })
+    }
+
+    fn field_per_charge(&self, _config: &mut FieldConfiguration, _x: f64, _y: f64) -> Result<Vec<Pair>, MaxwellError> {
+        Err(MaxwellError::Unsupported { solver: self.kind(), capability: SolverCapability::PerCharge })
+    }
+}
+
+/// Coulomb's law summed over the charges, extended sources and dipoles
+pub struct ElectrostaticDirectSolver;
+
+impl FieldSolver for ElectrostaticDirectSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::ElectrostaticDirect
+    }
+
+    fn supports(&self, _capability: SolverCapability) -> bool {
+        true
+    }
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        Ok(crate::compute_field_electrostatic_direct(config, x, y))
+    }
+
+    fn potential(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        Ok(crate::compute_potential_electrostatic_direct(config, x, y))
+    }
+
+    fn forces(&self, config: &mut FieldConfiguration) -> Result<Vec<Pair>, MaxwellError> {
+        Ok(crate::compute_forces_electrostatic(config))
+    }
+
+    fn field_per_charge(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Vec<Pair>, MaxwellError> {
+        Ok(crate::compute_field_electrostatic_per_charge_direct(config, x, y))
+    }
+}
+
+/// The Biot-Savart law summed over the out-of-plane currents and magnets.
+///
+/// Currents have no single-valued scalar potential, so there is no potential; the magnets alone have the scalar
+/// potential given by compute_potential_magnetostatic_scalar_direct.
+pub struct MagnetostaticDirectSolver;
+
+impl FieldSolver for MagnetostaticDirectSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::MagnetostaticDirect
+    }
+
+    fn supports(&self, capability: SolverCapability) -> bool {
+        capability != SolverCapability::Potential
+    }
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        Ok(crate::compute_field_magnetostatic_direct(config, x, y))
+    }
+
+    fn forces(&self, config: &mut FieldConfiguration) -> Result<Vec<Pair>, MaxwellError> {
+        Ok(crate::compute_forces_magnetostatic(config))
+    }
+
+    fn field_per_charge(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Vec<Pair>, MaxwellError> {
+        Ok(config.charges.iter().map(|charge| crate::compute_field_magnetostatic_direct_one_charge(config, charge, x, y)).collect())
+    }
+}
+
+/// Poisson's equation solved on the grid by FFT, solved again only when the charges change
+pub struct ElectrostaticFourierSolver;
+
+impl FieldSolver for ElectrostaticFourierSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::ElectrostaticFourier
+    }
+
+    fn supports(&self, capability: SolverCapability) -> bool {
+        capability != SolverCapability::PerCharge
+    }
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        config.spectral_field(x, y)
+    }
+
+    fn potential(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        config.spectral_potential(x, y)
+    }
+
+    fn forces(&self, config: &mut FieldConfiguration) -> Result<Vec<Pair>, MaxwellError> {
+        config.spectral_forces()
+    }
+}
+
+/// Poisson's equation solved on the grid by multigrid, with conductors and dielectrics
+pub struct ElectrostaticMultigridSolver;
+
+impl FieldSolver for ElectrostaticMultigridSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::ElectrostaticMultigrid
+    }
+
+    fn supports(&self, capability: SolverCapability) -> bool {
+        capability != SolverCapability::PerCharge
+    }
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        config.multigrid_field(x, y)
+    }
+
+    fn potential(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        config.multigrid_potential(x, y)
+    }
+
+    fn forces(&self, config: &mut FieldConfiguration) -> Result<Vec<Pair>, MaxwellError> {
+        config.multigrid_forces()
+    }
+}
+
+/// The vector potential of the out-of-plane currents solved on the grid by multigrid, with magnetic materials. The
+/// out-of-plane field of in-plane currents is available from FieldConfiguration::vector_potential_field_z.
+pub struct MagnetostaticMultigridSolver;
+
+impl FieldSolver for MagnetostaticMultigridSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::MagnetostaticMultigrid
+    }
+
+    fn supports(&self, capability: SolverCapability) -> bool {
+        matches!(capability, SolverCapability::Field | SolverCapability::Forces)
+    }
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        config.vector_potential_field(x, y)
+    }
+
+    fn forces(&self, config: &mut FieldConfiguration) -> Result<Vec<Pair>, MaxwellError> {
+        config.vector_potential_forces()
+    }
+}
+
+/// Maxwell's equations evolved on the grid by FieldConfiguration::tick
+pub struct DynamicSolver;
+
+impl FieldSolver for DynamicSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::Dynamic
+    }
+
+    fn supports(&self, capability: SolverCapability) -> bool {
+        capability == SolverCapability::Field
+    }
+
+    fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, MaxwellError> {
+        let (u, v) = config.evaluate_elec_interpolated(x, y)?;
+        Ok(Pair { u, v })
+    }
+}
+
+impl SolverKind {
+    pub fn field_solver(&self) -> &'static dyn FieldSolver {
+        match self {
+            SolverKind::ElectrostaticDirect => &ElectrostaticDirectSolver,
+            SolverKind::MagnetostaticDirect => &MagnetostaticDirectSolver,
+            SolverKind::ElectrostaticFourier => &ElectrostaticFourierSolver,
+            SolverKind::Dynamic => &DynamicSolver,
+            SolverKind::ElectrostaticMultigrid => &ElectrostaticMultigridSolver,
+            SolverKind::MagnetostaticMultigrid => &MagnetostaticMultigridSolver,
+        }
+    }
+}
+
+/// Any of the solvers, chosen by SolverKind, for use from JS.
+///
+/// For example, `const solver = new Solver(SolverKind.ElectrostaticDirect); solver.field_to_buffer(field, x, y, buffer)`.
+/// Calls that the solver does not support throw; check with supports() first.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Solver {
+    kind: SolverKind,
+}
+
+#[wasm_bindgen]
+impl Solver {
+    #[wasm_bindgen(constructor)]
+    pub fn new(kind: SolverKind) -> Solver {
+        Solver { kind }
+    }
+
+    /// The solver chosen when the configuration was built
+    pub fn for_configuration(config: &FieldConfiguration) -> Solver {
+        Solver { kind: config.get_solver_kind() }
+    }
+
+    pub fn get_kind(&self) -> SolverKind {
+        self.kind
+    }
+
+    pub fn supports(&self, capability: SolverCapability) -> bool {
+        self.kind.field_solver().supports(capability)
+    }
+
+    pub fn field(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<Pair, JsError> {
+        Ok(self.kind.field_solver().field(config, x, y)?)
+    }
+
+    pub fn field_to_buffer(&self, config: &mut FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) -> Result<(), JsError> {
+        let Pair { u, v } = self.kind.field_solver().field(config, x, y)?;
+        buffer[0] = u;
+        buffer[1] = v;
+        Ok(())
+    }
+
+    pub fn potential(&self, config: &mut FieldConfiguration, x: f64, y: f64) -> Result<f64, JsError> {
+        Ok(self.kind.field_solver().potential(config, x, y)?)
+    }
+
+    pub fn forces(&self, config: &mut FieldConfiguration) -> Result<Vec<Pair>, JsError> {
+        Ok(self.kind.field_solver().forces(config)?)
+    }
+
+    /// Write the field of each charge in turn into buffer, which must have room for two values per charge
+    pub fn field_per_charge_to_buffer(&self, config: &mut FieldConfiguration, x: f64, y: f64, buffer: &mut [f64]) -> Result<(), JsError> {
+        let fields = self.kind.field_solver().field_per_charge(config, x, y)?;
+        for (index, Pair { u, v }) in fields.into_iter().enumerate() {
+            buffer[2 * index] = u;
+            buffer[2 * index + 1] = v;
+        }
+        Ok(())
+    }
+}
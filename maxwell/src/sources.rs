@@ -0,0 +1,311 @@
+use serde::{Serialize, Deserialize};
+use ndarray::Array2;
+use crate::Pair;
+use crate::geometry::Geometry;
+use crate::softening::{SofteningKernel, ForceLaw};
+use crate::stencil::Stencils;
+
+/// A uniformly charged source with finite extent in the plane.
+///
+/// The fields are computed analytically for the same force law as point charges, softened by a Plummer length
+/// (normally the length of the global softening kernel) so that they remain finite on the source itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExtendedSource {
+    /// Straight line from (x0, y0) to (x1, y1), carrying charge per unit length line_density
+    Segment { x0: f64, y0: f64, x1: f64, y1: f64, line_density: f64 },
+    /// Circular arc centred on (x, y), running anticlockwise from start_angle to end_angle (in radians)
+    Arc { x: f64, y: f64, radius: f64, start_angle: f64, end_angle: f64, line_density: f64 },
+    /// Filled polygon with charge per unit area surface_density. Vertices may be in either winding order.
+    Polygon { vertices: Vec<(f64, f64)>, surface_density: f64 },
+}
+
+/// Integrals over a unit line density on the segment from a to b, as seen from p: for the inverse-square law,
+/// the potential $\int dt / \sqrt{r^2+\epsilon^2}$ and the field $\int (p-q) / (r^2+\epsilon^2)^{3/2} dt$, or for
+/// the logarithmic law $-\frac{1}{2}\int \ln(r^2+\epsilon^2) dt$ and $\int (p-q) / (r^2+\epsilon^2) dt$.
+///
+/// Also returns the signed perpendicular distance h of p from the line (positive to the right of a->b),
+/// and the along-line coordinates of a and b relative to the foot of the perpendicular, which the polygon
+/// formulae need.
+pub(crate) struct SegmentIntegrals {
+    pub potential: f64,
+    pub field: Pair,
+    pub h: f64,
+    pub t_a: f64,
+    pub t_b: f64,
+}
+
+pub(crate) fn segment_integrals(px: f64, py: f64, a: (f64, f64), b: (f64, f64), epsilon: f64, law: ForceLaw) -> SegmentIntegrals {
+    let (ex, ey) = (b.0 - a.0, b.1 - a.1);
+    let length = (ex * ex + ey * ey).sqrt();
+    if length == 0.0 {
+        return SegmentIntegrals { potential: 0.0, field: Pair { u: 0.0, v: 0.0 }, h: 0.0, t_a: 0.0, t_b: 0.0 };
+    }
+    let (ex, ey) = (ex / length, ey / length);
+    // normal pointing to the right of the direction of travel, i.e. outwards for an anticlockwise polygon
+    let (nx, ny) = (ey, -ex);
+
+    let t_a = (a.0 - px) * ex + (a.1 - py) * ey;
+    let t_b = t_a + length;
+    let h = (a.0 - px) * nx + (a.1 - py) * ny;
+
+    // on the line itself the unsoftened integrals diverge; keep them finite
+    let d2 = (h * h + epsilon * epsilon).max(1e-12);
+    let d = d2.sqrt();
+
+    let (potential, parallel, perpendicular) = match law {
+        ForceLaw::InverseSquare => {
+            let s_a = (t_a * t_a + d2).sqrt();
+            let s_b = (t_b * t_b + d2).sqrt();
+            ((t_b / d).asinh() - (t_a / d).asinh(), 1.0 / s_b - 1.0 / s_a, -h / d2 * (t_b / s_b - t_a / s_a))
+        },
+        ForceLaw::Logarithmic => {
+            let integral_of_log = |t: f64| t * (t * t + d2).ln() - 2.0 * t + 2.0 * d * (t / d).atan();
+            (-0.5 * (integral_of_log(t_b) - integral_of_log(t_a)),
+             -0.5 * ((t_b * t_b + d2) / (t_a * t_a + d2)).ln(),
+             -h / d * ((t_b / d).atan() - (t_a / d).atan()))
+        },
+    };
+
+    SegmentIntegrals {
+        potential,
+        field: Pair { u: parallel * ex + perpendicular * nx, v: parallel * ey + perpendicular * ny },
+        h, t_a, t_b
+    }
+}
+
+impl ExtendedSource {
+    /// Split an arc into straight chords, each carrying the charge of the piece of arc it replaces.
+    ///
+    /// The number of chords is chosen so that the sagitta is a small fraction of the radius.
+    fn arc_to_segments(x: f64, y: f64, radius: f64, start_angle: f64, end_angle: f64, line_density: f64) -> Vec<ExtendedSource> {
+        const MAX_CHORDS: usize = 256;
+        const SAGITTA_TOLERANCE: f64 = 2e-4;
+        let sweep = end_angle - start_angle;
+        let max_angle = (8.0 * SAGITTA_TOLERANCE).sqrt();
+        let n = ((sweep.abs() / max_angle).ceil() as usize).clamp(1, MAX_CHORDS);
+        let delta = sweep / n as f64;
+        let chord_density = if delta == 0.0 { 0.0 } else { line_density * delta.abs() / (2.0 * (0.5 * delta).sin().abs()) };
+
+        (0..n).map(|k| {
+            let theta0 = start_angle + k as f64 * delta;
+            let theta1 = theta0 + delta;
+            ExtendedSource::Segment {
+                x0: x + radius * theta0.cos(), y0: y + radius * theta0.sin(),
+                x1: x + radius * theta1.cos(), y1: y + radius * theta1.sin(),
+                line_density: chord_density
+            }
+        }).collect()
+    }
+
+    /// +1 for anticlockwise polygons, -1 for clockwise ones
+    pub(crate) fn polygon_orientation(vertices: &[(f64, f64)]) -> f64 {
+        let twice_area: f64 = polygon_edges(vertices).map(|(a, b)| a.0 * b.1 - b.0 * a.1).sum();
+        if twice_area < 0.0 { -1.0 } else { 1.0 }
+    }
+
+    /// The potential integral at (x, y), without the overall field scaling; the potential is minus this
+    pub fn potential(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> f64 {
+        match self {
+            ExtendedSource::Segment { x0, y0, x1, y1, line_density } => {
+                line_density * segment_integrals(x, y, (*x0, *y0), (*x1, *y1), epsilon, law).potential
+            },
+            ExtendedSource::Arc { x: xc, y: yc, radius, start_angle, end_angle, line_density } => {
+                Self::arc_to_segments(*xc, *yc, *radius, *start_angle, *end_angle, *line_density).iter()
+                    .map(|segment| segment.potential(x, y, epsilon, law)).sum()
+            },
+            ExtendedSource::Polygon { vertices, surface_density } if law == ForceLaw::Logarithmic => {
+                let orientation = Self::polygon_orientation(vertices);
+                let total: f64 = polygon_edges(vertices)
+                    .map(|(a, b)| orientation * logarithmic_polygon_edge_integral(x, y, a, b, epsilon)).sum();
+                surface_density * total
+            },
+            ExtendedSource::Polygon { vertices, surface_density } => {
+                // In polar coordinates about (x,y), the area integral of $1/\sqrt{r^2+\epsilon^2}$ becomes a sum over
+                // edges of closed-form integrals along each edge.
+                let orientation = Self::polygon_orientation(vertices);
+                let mut total = 0.0;
+                for (a, b) in polygon_edges(vertices) {
+                    let seg = segment_integrals(x, y, a, b, epsilon, law);
+                    let h = orientation * seg.h;
+                    total += h * seg.potential;
+                    if epsilon > 0.0 && h != 0.0 {
+                        let d2 = h * h + epsilon * epsilon;
+                        let angle_term = |t: f64| (epsilon * t / (h * (t * t + d2).sqrt())).atan() - (t / h).atan();
+                        total += epsilon * (angle_term(seg.t_b) - angle_term(seg.t_a));
+                    }
+                }
+                surface_density * total
+            }
+        }
+    }
+
+    /// The field at (x, y), without the overall field scaling
+    pub fn field(&self, x: f64, y: f64, epsilon: f64, law: ForceLaw) -> Pair {
+        match self {
+            ExtendedSource::Segment { x0, y0, x1, y1, line_density } => {
+                let Pair { u, v } = segment_integrals(x, y, (*x0, *y0), (*x1, *y1), epsilon, law).field;
+                Pair { u: line_density * u, v: line_density * v }
+            },
+            ExtendedSource::Arc { x: xc, y: yc, radius, start_angle, end_angle, line_density } => {
+                let mut result = Pair { u: 0.0, v: 0.0 };
+                for segment in Self::arc_to_segments(*xc, *yc, *radius, *start_angle, *end_angle, *line_density) {
+                    let Pair { u, v } = segment.field(x, y, epsilon, law);
+                    result.u += u;
+                    result.v += v;
+                }
+                result
+            },
+            ExtendedSource::Polygon { vertices, surface_density } => {
+                // By the divergence theorem, the field of a uniform sheet is the outward normal of each edge
+                // weighted by that edge's potential integral.
+                let orientation = Self::polygon_orientation(vertices);
+                let mut result = Pair { u: 0.0, v: 0.0 };
+                for (a, b) in polygon_edges(vertices) {
+                    let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+                    if length == 0.0 {
+                        continue;
+                    }
+                    let (nx, ny) = (orientation * (b.1 - a.1) / length, -orientation * (b.0 - a.0) / length);
+                    let potential = segment_integrals(x, y, a, b, epsilon, law).potential;
+                    result.u += nx * potential;
+                    result.v += ny * potential;
+                }
+                Pair { u: surface_density * result.u, v: surface_density * result.v }
+            }
+        }
+    }
+
+    /// Total charge carried by the source
+    pub fn total_charge(&self) -> f64 {
+        match self {
+            ExtendedSource::Segment { x0, y0, x1, y1, line_density } => line_density * ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt(),
+            ExtendedSource::Arc { radius, start_angle, end_angle, line_density, .. } => line_density * radius * (end_angle - start_angle).abs(),
+            ExtendedSource::Polygon { vertices, surface_density } => {
+                let twice_area: f64 = polygon_edges(vertices).map(|(a, b)| a.0 * b.1 - b.0 * a.1).sum();
+                surface_density * 0.5 * twice_area.abs()
+            }
+        }
+    }
+
+    /// Add the charge density of the source onto the grid.
+    ///
+    /// Line sources are sampled at intervals of half a cell, each sample being softened by the kernel; filled
+    /// polygons are rasterised with sub-cell sampling to anti-alias their edges.
+    pub fn deposit(&self, grid: &mut Array2<f64>, geometry: &Geometry, stencils: &Stencils, kernel: &SofteningKernel, normalization: f64) {
+        match self {
+            ExtendedSource::Segment { x0, y0, x1, y1, line_density } => {
+                let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+                let spacing = 0.5 * geometry.delta_x().min(geometry.delta_y());
+                let n = ((length / spacing).ceil() as usize).max(1);
+                let charge_per_sample = line_density * length / n as f64;
+                for k in 0..n {
+                    let t = (k as f64 + 0.5) / n as f64;
+                    let x = x0 + t * (x1 - x0);
+                    let y = y0 + t * (y1 - y0);
+                    if let Some((i, j)) = geometry.position_to_cell(x, y) {
+                        stencils.add_softened_point(grid, i, j, charge_per_sample * normalization, kernel);
+                    }
+                }
+            },
+            ExtendedSource::Arc { x, y, radius, start_angle, end_angle, line_density } => {
+                for segment in Self::arc_to_segments(*x, *y, *radius, *start_angle, *end_angle, *line_density) {
+                    segment.deposit(grid, geometry, stencils, kernel, normalization);
+                }
+            },
+            ExtendedSource::Polygon { vertices, surface_density } => {
+                const SUBSAMPLES: usize = 4;
+                let dx = geometry.delta_x();
+                let dy = geometry.delta_y();
+                let (x_min, x_max) = vertices.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v.0), hi.max(v.0)));
+                let (y_min, y_max) = vertices.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v.1), hi.max(v.1)));
+                let (i_min, j_min) = geometry.position_to_cell_unclamped(x_min, y_min);
+                let (i_max, j_max) = geometry.position_to_cell_unclamped(x_max, y_max);
+                let charge_per_subsample = surface_density * dx * dy / (SUBSAMPLES * SUBSAMPLES) as f64;
+
+                for i in i_min.max(0)..(i_max + 1).min(geometry.nx as isize) {
+                    for j in j_min.max(0)..(j_max + 1).min(geometry.ny as isize) {
+                        let (x0, y0) = geometry.cell_to_corners(i as usize, j as usize)[0];
+                        let mut inside = 0;
+                        for si in 0..SUBSAMPLES {
+                            for sj in 0..SUBSAMPLES {
+                                let x = x0 + (si as f64 + 0.5) * dx / SUBSAMPLES as f64;
+                                let y = y0 + (sj as f64 + 0.5) * dy / SUBSAMPLES as f64;
+                                if point_in_polygon(vertices, x, y) {
+                                    inside += 1;
+                                }
+                            }
+                        }
+                        grid[[i as usize, j as usize]] += inside as f64 * charge_per_subsample * normalization;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The contribution of one edge to the logarithmic potential of a uniform polygon, seen from p.
+///
+/// In polar coordinates about p, the area integral of $h(r) = -\frac{1}{2}\ln(r^2+\epsilon^2)$ over the triangle
+/// between p and the edge is $\int h_\perp F(r) / r^2 dt$ along the edge, with $F(r) = \int_0^r h(\rho) \rho d\rho$.
+/// All but a bounded softening correction to $F(r)/r^2$ integrates in closed form; the rest is done by quadrature.
+fn logarithmic_polygon_edge_integral(px: f64, py: f64, a: (f64, f64), b: (f64, f64), epsilon: f64) -> f64 {
+    let seg = segment_integrals(px, py, a, b, epsilon, ForceLaw::Logarithmic);
+    if seg.h == 0.0 {
+        return 0.0;
+    }
+    let d2 = seg.h * seg.h + epsilon * epsilon;
+    let d = d2.sqrt();
+    // $\int (\ln(t^2+d^2) - 1) dt$
+    let closed_form = |t: f64| t * (t * t + d2).ln() - 3.0 * t + 2.0 * d * (t / d).atan();
+    let mut integral = closed_form(seg.t_b) - closed_form(seg.t_a);
+    if epsilon > 0.0 {
+        let e2 = epsilon * epsilon;
+        let length = seg.t_b - seg.t_a;
+        let correction: f64 = quadrature_along_segment((seg.t_a, 0.0), (seg.t_b, 0.0), seg.h.abs().max(epsilon)).iter()
+            .map(|(t, _, weight)| {
+                let r2 = t * t + seg.h * seg.h;
+                weight * e2 * (r2 / e2).ln_1p() / r2
+            }).sum();
+        integral += length * correction;
+    }
+    -0.25 * seg.h * integral
+}
+
+/// Gauss-Legendre quadrature points (x, y, weight) along the segment from a to b, with weights summing to one.
+///
+/// The segment is divided into sub-pieces no longer than max_piece_length (up to a limit), each integrated
+/// with four points, so that fields varying on that scale are resolved.
+pub(crate) fn quadrature_along_segment(a: (f64, f64), b: (f64, f64), max_piece_length: f64) -> Vec<(f64, f64, f64)> {
+    const GAUSS_POINTS: [f64; 4] = [-0.861136311594053, -0.339981043584856, 0.339981043584856, 0.861136311594053];
+    const GAUSS_WEIGHTS: [f64; 4] = [0.347854845137454, 0.652145154862546, 0.652145154862546, 0.347854845137454];
+    const MAX_SUBDIVISIONS: usize = 64;
+
+    let (lx, ly) = (b.0 - a.0, b.1 - a.1);
+    let length = (lx * lx + ly * ly).sqrt();
+    let n_sub = ((length / max_piece_length).ceil() as usize).clamp(1, MAX_SUBDIVISIONS);
+    let mut points = Vec::with_capacity(4 * n_sub);
+    for sub in 0..n_sub {
+        for (point, weight) in GAUSS_POINTS.iter().zip(GAUSS_WEIGHTS.iter()) {
+            let t = (sub as f64 + 0.5 * (point + 1.0)) / n_sub as f64;
+            points.push((a.0 + t * lx, a.1 + t * ly, weight * 0.5 / n_sub as f64));
+        }
+    }
+    points
+}
+
+/// Iterate over the edges of a closed polygon, including the one from the last vertex back to the first
+pub(crate) fn polygon_edges(vertices: &[(f64, f64)]) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    vertices.iter().zip(vertices.iter().cycle().skip(1)).map(|(a, b)| (*a, *b))
+}
+
+/// Even-odd rule test for whether (x, y) lies inside the polygon
+pub fn point_in_polygon(vertices: &[(f64, f64)], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for (a, b) in polygon_edges(vertices) {
+        if (a.1 > y) != (b.1 > y) && x < a.0 + (y - a.1) * (b.0 - a.0) / (b.1 - a.1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
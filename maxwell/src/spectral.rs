@@ -0,0 +1,25 @@
+use crate::FieldConfiguration;
+use crate::error::MaxwellError;
+use crate::grid_solution::GridSolution;
+use crate::stencil::{StencilType, DifferenceType};
+
+/// The static potential and electric field of the charges and extended sources, found by solving Poisson's equation
+/// on the grid with Stencils::del_squared_inv.
+///
+/// The grid is periodic, and its mean potential is zero, unless the boundaries are isolated, in which case the
+/// potential is that of open space and matches the direct solvers. The field is the central difference of the
+/// potential, so that both live at the cell centroids and a charge exerts no force on itself at the centroid of its
+/// own cell.
+pub fn solve(config: &FieldConfiguration) -> Result<GridSolution, MaxwellError> {
+    let density = config.charge_density_on_grid();
+    let mut potential = density.clone();
+    config.stencils.apply(&mut potential, StencilType::DelSquaredInv, DifferenceType::Central)?;
+
+    // Differencing the potential separately would wrap around at the edges of an isolated grid
+    let mut elec_x = density.clone();
+    let mut elec_y = density;
+    config.stencils.apply(&mut elec_x, StencilType::GradXDelSquaredInv, DifferenceType::Central)?;
+    config.stencils.apply(&mut elec_y, StencilType::GradYDelSquaredInv, DifferenceType::Central)?;
+
+    Ok(GridSolution::new(&config.charges, potential, elec_x, elec_y))
+}
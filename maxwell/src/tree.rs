@@ -0,0 +1,238 @@
+use crate::{Charge, Pair};
+use crate::softening::{SofteningKernel, ForceLaw};
+
+/// Leaves hold up to this many charges, which are then summed directly
+const MAX_CHARGES_PER_LEAF: usize = 8;
+/// Prevents unbounded recursion when many charges sit at the same point
+const MAX_DEPTH: usize = 32;
+/// Nodes closer than this many softening lengths are always opened, since the expansion assumes unsoftened charges
+const SOFTENING_SAFETY_FACTOR: f64 = 4.0;
+
+/// A node of the quadtree, carrying the multipole moments of the charges below it up to the quadrupole
+pub struct TreeNode {
+    x_min: f64,
+    y_min: f64,
+    size: f64,
+    /// Total charge of the node
+    charge: f64,
+    /// Centre of the multipole expansion (the centroid weighted by |charge|)
+    centre: (f64, f64),
+    /// Dipole moment about the centre
+    dipole: (f64, f64),
+    /// Second moments (xx, xy, yy) of the charge about the centre, from which the quadrupole moment is formed
+    second_moments: (f64, f64, f64),
+    /// Largest softening length of any charge in the node
+    max_softening: f64,
+    children: Option<[usize; 4]>,
+    members: Vec<usize>,
+}
+
+impl TreeNode {
+    /// The traceless quadrupole moment (xx, xy, yy) in the plane, $\sum q (n s_i s_j - s^2 \delta_{ij})$ with n = 3
+    /// for the inverse-square law and n = 2 for the logarithmic law
+    fn quadrupole(&self, law: ForceLaw) -> (f64, f64, f64) {
+        let n = match law {
+            ForceLaw::InverseSquare => 3.0,
+            ForceLaw::Logarithmic => 2.0,
+        };
+        let (sxx, sxy, syy) = self.second_moments;
+        (n * sxx - (sxx + syy), n * sxy, n * syy - (sxx + syy))
+    }
+
+    /// The multipole expansion of the potential at (x, y), without the overall field scaling and with the same sign
+    /// convention as SofteningKernel::potential_factor
+    pub fn potential(&self, x: f64, y: f64, law: ForceLaw) -> f64 {
+        let (dx, dy) = (x - self.centre.0, y - self.centre.1);
+        let r2 = dx * dx + dy * dy;
+        let (qxx, qxy, qyy) = self.quadrupole(law);
+        let p_dot_r = self.dipole.0 * dx + self.dipole.1 * dy;
+        let r_q_r = qxx * dx * dx + 2.0 * qxy * dx * dy + qyy * dy * dy;
+        match law {
+            ForceLaw::InverseSquare => {
+                let r = r2.sqrt();
+                self.charge / r + p_dot_r / (r2 * r) + r_q_r / (2.0 * r2 * r2 * r)
+            },
+            ForceLaw::Logarithmic => -0.5 * self.charge * r2.ln() + p_dot_r / r2 + r_q_r / (2.0 * r2 * r2),
+        }
+    }
+
+    /// The multipole expansion of the field at (x, y), without the overall field scaling
+    pub fn field(&self, x: f64, y: f64, law: ForceLaw) -> Pair {
+        let (dx, dy) = (x - self.centre.0, y - self.centre.1);
+        let r2 = dx * dx + dy * dy;
+        let (px, py) = self.dipole;
+        let (qxx, qxy, qyy) = self.quadrupole(law);
+        let p_dot_r = px * dx + py * dy;
+        let (qrx, qry) = (qxx * dx + qxy * dy, qxy * dx + qyy * dy);
+        let r_q_r = qrx * dx + qry * dy;
+        // minus the gradient of each term: a part along r from its denominator, and a part from its numerator
+        let (radial, dipole_scale, quadrupole_scale) = match law {
+            ForceLaw::InverseSquare => {
+                let r3 = r2 * r2.sqrt();
+                let r5 = r3 * r2;
+                (self.charge / r3 + 3.0 * p_dot_r / r5 + 2.5 * r_q_r / (r5 * r2), 1.0 / r3, 1.0 / r5)
+            },
+            ForceLaw::Logarithmic => {
+                let r4 = r2 * r2;
+                (self.charge / r2 + 2.0 * p_dot_r / r4 + 2.0 * r_q_r / (r4 * r2), 1.0 / r2, 1.0 / r4)
+            },
+        };
+        Pair {
+            u: radial * dx - px * dipole_scale - qrx * quadrupole_scale,
+            v: radial * dy - py * dipole_scale - qry * quadrupole_scale,
+        }
+    }
+}
+
+/// Barnes-Hut quadtree over the point charges, used to evaluate fields and potentials in $O(\log N)$ time.
+///
+/// Distant nodes are replaced by their multipole expansion when their size is less than the opening angle
+/// theta times their distance; smaller theta is more accurate, and theta = 0 reproduces the direct sum.
+pub struct ChargeTree {
+    nodes: Vec<TreeNode>,
+}
+
+impl ChargeTree {
+    pub fn new(charges: &[Charge], kernel: &SofteningKernel) -> ChargeTree {
+        let mut tree = ChargeTree { nodes: vec![] };
+        if charges.is_empty() {
+            return tree;
+        }
+        let (mut x_min, mut y_min) = (f64::INFINITY, f64::INFINITY);
+        let (mut x_max, mut y_max) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for charge in charges {
+            x_min = x_min.min(charge.x);
+            y_min = y_min.min(charge.y);
+            x_max = x_max.max(charge.x);
+            y_max = y_max.max(charge.y);
+        }
+        // pad slightly so that charges on the upper edges fall inside the box
+        let size = (x_max - x_min).max(y_max - y_min).max(1e-6) * (1.0 + 1e-9);
+        tree.build(charges, kernel, (0..charges.len()).collect(), (x_min, y_min), size, 0);
+        tree
+    }
+
+    fn build(&mut self, charges: &[Charge], kernel: &SofteningKernel, members: Vec<usize>, corner: (f64, f64), size: f64, depth: usize) -> usize {
+        let (x_min, y_min) = corner;
+        let mut charge = 0.0;
+        let mut abs_charge = 0.0;
+        let (mut cx, mut cy) = (0.0, 0.0);
+        let mut max_softening: f64 = 0.0;
+        for &i in &members {
+            charge += charges[i].charge;
+            abs_charge += charges[i].charge.abs();
+            cx += charges[i].charge.abs() * charges[i].x;
+            cy += charges[i].charge.abs() * charges[i].y;
+            max_softening = max_softening.max(kernel.for_charge(&charges[i]).length());
+        }
+        let centre = if abs_charge > 0.0 { (cx / abs_charge, cy / abs_charge) } else { (x_min + 0.5 * size, y_min + 0.5 * size) };
+        let mut dipole = (0.0, 0.0);
+        let mut second_moments = (0.0, 0.0, 0.0);
+        for &i in &members {
+            let q = charges[i].charge;
+            let (sx, sy) = (charges[i].x - centre.0, charges[i].y - centre.1);
+            dipole.0 += q * sx;
+            dipole.1 += q * sy;
+            second_moments.0 += q * sx * sx;
+            second_moments.1 += q * sx * sy;
+            second_moments.2 += q * sy * sy;
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(TreeNode { x_min, y_min, size, charge, centre, dipole, second_moments, max_softening, children: None, members: vec![] });
+
+        if members.len() <= MAX_CHARGES_PER_LEAF || depth >= MAX_DEPTH {
+            self.nodes[index].members = members;
+            return index;
+        }
+
+        let half = 0.5 * size;
+        let mut quadrants: [Vec<usize>; 4] = Default::default();
+        for i in members {
+            let qx = if charges[i].x >= x_min + half { 1 } else { 0 };
+            let qy = if charges[i].y >= y_min + half { 1 } else { 0 };
+            quadrants[qx + 2 * qy].push(i);
+        }
+        let mut children = [0; 4];
+        for (q, quadrant_members) in quadrants.into_iter().enumerate() {
+            let child_x = x_min + if q % 2 == 1 { half } else { 0.0 };
+            let child_y = y_min + if q / 2 == 1 { half } else { 0.0 };
+            children[q] = self.build(charges, kernel, quadrant_members, (child_x, child_y), half, depth + 1);
+        }
+        self.nodes[index].children = Some(children);
+        index
+    }
+
+    /// Walk the tree for an evaluation at (x, y), calling near(i) for each charge that must be summed directly and
+    /// far(node) for each node that can be replaced by its multipole expansion.
+    pub fn walk(&self, x: f64, y: f64, theta: f64, mut near: impl FnMut(usize), mut far: impl FnMut(&TreeNode)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.children.is_none() && node.members.is_empty() {
+                continue;
+            }
+            let inside = x >= node.x_min && x <= node.x_min + node.size && y >= node.y_min && y <= node.y_min + node.size;
+            let distance = ((x - node.centre.0).powi(2) + (y - node.centre.1).powi(2)).sqrt();
+            let acceptable = !inside && node.size < theta * distance && distance > SOFTENING_SAFETY_FACTOR * node.max_softening;
+            if acceptable {
+                far(node);
+            } else if let Some(children) = node.children {
+                stack.extend_from_slice(&children);
+            } else {
+                node.members.iter().for_each(|&i| near(i));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FieldConfiguration, compute_forces_electrostatic};
+    use crate::builder::{FieldConfigurationBuilder, SolverKind};
+
+    /// A few hundred charges of either sign, scattered by a linear congruential generator so the test is repeatable
+    fn configuration(law: ForceLaw) -> FieldConfiguration {
+        let mut config = FieldConfigurationBuilder::new(500.0, 500.0, 100, 100)
+            .solver(SolverKind::ElectrostaticDirect)
+            .force_law(law)
+            .try_build()
+            .unwrap();
+        let mut state: u64 = 12345;
+        let mut random = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        config.charges = (0..300).map(|_| Charge::new(500.0 * random(), 500.0 * random(), 2.0 * random() - 1.0)).collect();
+        config.charges_changed();
+        config
+    }
+
+    /// The root-mean-square error of the forces at theta = 0.5, relative to the root-mean-square exact force
+    fn relative_rms_force_error(law: ForceLaw) -> f64 {
+        let mut config = configuration(law);
+        let exact = compute_forces_electrostatic(&config);
+        config.set_tree_opening_angle(0.5);
+        let approximate = compute_forces_electrostatic(&config);
+        let error: f64 = exact.iter().zip(&approximate).map(|(e, a)| (e.u - a.u).powi(2) + (e.v - a.v).powi(2)).sum();
+        let norm: f64 = exact.iter().map(|e| e.u * e.u + e.v * e.v).sum();
+        (error / norm).sqrt()
+    }
+
+    // These bound the errors quoted by FieldConfiguration::set_tree_opening_angle
+    #[test]
+    fn inverse_square_forces_match_direct_sum() {
+        let error = relative_rms_force_error(ForceLaw::InverseSquare);
+        assert!(error < 1e-3, "relative RMS force error {}", error);
+    }
+
+    #[test]
+    fn logarithmic_forces_match_direct_sum() {
+        let error = relative_rms_force_error(ForceLaw::Logarithmic);
+        assert!(error < 5e-3, "relative RMS force error {}", error);
+    }
+}
@@ -0,0 +1,117 @@
+use serde::{Serialize, Deserialize};
+use wasm_bindgen::prelude::*;
+use crate::softening::ForceLaw;
+
+/// In screen units, the strength of the inverse-square interaction, chosen to give a pleasing visualisation
+const SCREEN_FIELD_SCALING: f64 = 20000.0;
+/// In screen units, the charge normalisation of the grid solvers, which solve the two-dimensional Poisson equation.
+/// With the logarithmic law, the direct solvers are scaled to match.
+const SCREEN_LINE_CHARGE_SCALING: f64 = 4000.0;
+/// In screen units, the simulated time per millisecond of animation (light moves at 200 pixels per second)
+const SCREEN_TIME_PER_MILLISECOND: f64 = 0.2;
+/// In physical units, animations are slowed down so that light crosses the domain in this many milliseconds
+const LIGHT_CROSSING_MILLISECONDS: f64 = 4000.0;
+
+const SPEED_OF_LIGHT_SI: f64 = 299792458.0;
+/// $1/(4\pi\epsilon_0)$ in SI units
+const COULOMB_CONSTANT_SI: f64 = 8.9875517923e9;
+
+/// The units in which positions, charges, fields and times are given to and returned by FieldConfiguration
+///
+/// In the physical systems, the geometry, positions and softening lengths are in metres (SI) or centimetres
+/// (Gaussian), and time is in seconds. Charges are in coulombs or statcoulombs (per unit length for the logarithmic
+/// law, which describes line charges), electric fields in V/m or statvolt/cm, and magnetic fields in tesla or gauss.
+/// Currents in the magnetostatic solvers are given in place of charges, in amperes or statamperes.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitSystem {
+    /// The historical units of the visualisation, with lengths in pixels and the speed of light equal to one
+    #[default]
+    Screen,
+    #[serde(rename = "si")]
+    SI,
+    Gaussian,
+}
+
+impl UnitSystem {
+    /// The constant $k$ in Coulomb's law $E = kq/r^2$
+    pub fn coulomb_constant(&self) -> f64 {
+        match self {
+            UnitSystem::Screen => SCREEN_FIELD_SCALING,
+            UnitSystem::SI => COULOMB_CONSTANT_SI,
+            UnitSystem::Gaussian => 1.0,
+        }
+    }
+
+    /// The speed of light, in length units per time unit
+    pub fn speed_of_light(&self) -> f64 {
+        match self {
+            UnitSystem::Screen => 1.0,
+            UnitSystem::SI => SPEED_OF_LIGHT_SI,
+            UnitSystem::Gaussian => 100.0 * SPEED_OF_LIGHT_SI,
+        }
+    }
+
+    /// The permeability of the vacuum, relating $B = \mu_0 H$ outside magnetised matter
+    pub fn vacuum_permeability(&self) -> f64 {
+        match self {
+            UnitSystem::SI => 4.0 * std::f64::consts::PI * COULOMB_CONSTANT_SI / (SPEED_OF_LIGHT_SI * SPEED_OF_LIGHT_SI),
+            _ => 1.0,
+        }
+    }
+
+    /// The overall strength of the interaction, such that a unit charge has the field $S g(r) \vec{r}$
+    pub fn field_scaling(&self, law: ForceLaw) -> f64 {
+        match (self, law) {
+            (_, ForceLaw::InverseSquare) => self.coulomb_constant(),
+            // the grid's Poisson solve gives a unit charge the potential $\ln(r) / 2\pi$
+            (UnitSystem::Screen, ForceLaw::Logarithmic) => SCREEN_LINE_CHARGE_SCALING / (2.0 * std::f64::consts::PI),
+            // a line charge has the field $2k\lambda/r$
+            (_, ForceLaw::Logarithmic) => 2.0 * self.coulomb_constant(),
+        }
+    }
+
+    /// The charge deposited on the grid for a unit charge, chosen so that the grid and direct solvers agree
+    pub fn grid_charge_scaling(&self, law: ForceLaw) -> f64 {
+        match law {
+            // the inverse-square Green's function on the grid is exactly $-1/r$
+            ForceLaw::InverseSquare => self.field_scaling(law),
+            ForceLaw::Logarithmic => 2.0 * std::f64::consts::PI * self.field_scaling(law),
+        }
+    }
+
+    /// The factor converting the field of a charge into the magnetic field $B$ of a current of the same size, via
+    /// $\vec{B} = f \hat{z} \times \vec{E}$ (i.e. $1/c^2$ in SI and $1/c$ in Gaussian units)
+    pub fn current_field_factor(&self) -> f64 {
+        match self {
+            UnitSystem::Screen => 1.0,
+            UnitSystem::SI => 1.0 / (SPEED_OF_LIGHT_SI * SPEED_OF_LIGHT_SI),
+            UnitSystem::Gaussian => 1.0 / self.speed_of_light(),
+        }
+    }
+
+    /// The factor in the force $f I \vec{l} \times \vec{B}$ on a current (i.e. $1/c$ in Gaussian units)
+    pub fn current_force_factor(&self) -> f64 {
+        match self {
+            UnitSystem::Gaussian => 1.0 / self.speed_of_light(),
+            _ => 1.0,
+        }
+    }
+
+    /// The overall strength of the field $B$ of a magnetised region or magnetic dipole, in place of field_scaling
+    pub fn magnet_scaling(&self, law: ForceLaw) -> f64 {
+        match self {
+            UnitSystem::SI => self.field_scaling(law) / (SPEED_OF_LIGHT_SI * SPEED_OF_LIGHT_SI),
+            _ => self.field_scaling(law),
+        }
+    }
+
+    /// The simulated time per millisecond of animation, for a domain of width x_max
+    pub fn default_time_per_millisecond(&self, x_max: f64) -> f64 {
+        match self {
+            UnitSystem::Screen => SCREEN_TIME_PER_MILLISECOND,
+            _ => x_max / (self.speed_of_light() * LIGHT_CROSSING_MILLISECONDS),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use ndarray::Array2;
+use serde::{Serialize, Deserialize};
+use crate::{FieldConfiguration, interpolate_grid};
+use crate::builder::ConfigurationError;
+use crate::error::MaxwellError;
+use crate::geometry::Geometry;
+use crate::grid_solution::{GridSolution, check_on_grid};
+use crate::multigrid::{EdgeCondition, MultigridOperator, PotentialBoundary, Region, rasterise};
+use crate::softening::ForceLaw;
+
+/// A region with a relative permeability other than one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MagneticMaterial {
+    pub region: Region,
+    pub permeability: f64,
+}
+
+/// A current density given cell by cell, as current per unit area. The z component flows out of the plane in the
+/// same sense as the currents given as charges, and x and y flow in the plane.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurrentDensity {
+    pub x: Array2<f64>,
+    pub y: Array2<f64>,
+    pub z: Array2<f64>,
+}
+
+/// The static magnetic field of the out-of-plane currents, the current density and the uniform field, with magnetic
+/// materials, found on the grid by multigrid.
+///
+/// The out-of-plane currents give the in-plane field through the vector potential $A_z$, which solves
+/// $\nabla\cdot(\mu^{-1}\nabla A_z) = f\rho$ with $\rho$ the currents deposited like charges and f the units'
+/// current_field_factor, so that $\vec{B} = (\partial_y A_z, -\partial_x A_z)$ matches the direct solver in vacuum.
+/// Its contours are the field lines. The in-plane currents give $B_z = \mu H_z$, where
+/// $\nabla^2 H_z = -(\nabla\times\vec{J})_z$ and $H_z$ vanishes at the edge of the grid.
+///
+/// The uniform field enters through the outer boundary condition on $A_z$, as for the electrostatic multigrid solver.
+/// Wires and permanent magnets are left out.
+pub struct VectorPotentialSolution {
+    /// The vector potential $A_z$ of the currents that were solved for, with the in-plane field
+    grid: GridSolution,
+    mag_z: Array2<f64>,
+}
+
+impl VectorPotentialSolution {
+    /// Solve for the configuration's currents, starting from a previous solution if there is one
+    pub fn new(config: &FieldConfiguration, previous: Option<VectorPotentialSolution>) -> Result<VectorPotentialSolution, MaxwellError> {
+        if config.force_law != ForceLaw::Logarithmic {
+            return Err(ConfigurationError::UnsupportedForceLaw { solver: config.solver, law: config.force_law }.into());
+        }
+        let geometry = &config.geometry;
+        let (nx, ny) = (geometry.nx, geometry.ny);
+        let (dx, dy) = (geometry.delta_x(), geometry.delta_y());
+
+        let permeability = rasterise(geometry, config.magnetic_materials.iter().map(|material| &material.region))
+            .map(|index| index.map_or(1.0, |index| config.magnetic_materials[index].permeability));
+        let reluctivity = permeability.mapv(|mu| 1.0 / mu);
+
+        let field_factor = config.units.current_field_factor();
+        let grid_charge_scaling = config.units.grid_charge_scaling(config.force_law);
+        let mut sources = config.point_charge_density_on_grid();
+        if let Some(density) = &config.current_density {
+            sources.scaled_add(grid_charge_scaling, &density.z);
+        }
+        sources *= field_factor;
+        let edge = EdgeCondition::new(config);
+        edge.add_to_sources(&mut sources, &reluctivity, geometry);
+
+        let mut vector_potential = match previous {
+            Some(previous) => previous.grid.into_potential_grid(),
+            None => Array2::<f64>::zeros((nx, ny)),
+        };
+        let no_fixed = Array2::<bool>::from_elem((nx, ny), false);
+        let operator = MultigridOperator::new(reluctivity, no_fixed.clone(), dx, dy, config.potential_boundary, 0.0);
+        if let Some(warning) = operator.solve(&mut vector_potential, &sources) {
+            warning.report();
+        }
+        let (grad_x, grad_y) = edge.gradient(&vector_potential, geometry);
+
+        let mut mag_z = Array2::<f64>::zeros((nx, ny));
+        if let Some(density) = &config.current_density {
+            let mut curl = Array2::<f64>::zeros((nx, ny));
+            for ((i, j), curl) in curl.indexed_iter_mut() {
+                *curl = central_difference(&density.x, i, j, (0, 1), dy) - central_difference(&density.y, i, j, (1, 0), dx);
+            }
+            let operator = MultigridOperator::new(Array2::<f64>::ones((nx, ny)), no_fixed, dx, dy, PotentialBoundary::Dirichlet, 0.0);
+            if let Some(warning) = operator.solve(&mut mag_z, &(curl * grid_charge_scaling)) {
+                warning.report();
+            }
+            mag_z *= field_factor;
+            mag_z *= &permeability;
+        }
+
+        Ok(VectorPotentialSolution { grid: GridSolution::new(&config.charges, vector_potential, grad_y, -grad_x), mag_z })
+    }
+
+    /// The out-of-plane field $B_z$ at (x, y), anywhere on the grid including the boundary cells
+    pub fn field_z(&self, geometry: &Geometry, x: f64, y: f64) -> Result<f64, MaxwellError> {
+        check_on_grid(geometry, x, y)?;
+        Ok(interpolate_grid(geometry, &self.mag_z, x, y))
+    }
+}
+
+/// The vector potential $A_z$ and the in-plane field on the grid
+impl AsRef<GridSolution> for VectorPotentialSolution {
+    fn as_ref(&self) -> &GridSolution {
+        &self.grid
+    }
+}
+
+/// The central difference of grid at (i, j) along the offset (0, 1) or (1, 0), taking it to be zero beyond the edge
+fn central_difference(grid: &Array2<f64>, i: usize, j: usize, (di, dj): (usize, usize), h: f64) -> f64 {
+    let (nx, ny) = grid.dim();
+    let high = if i + di < nx && j + dj < ny { grid[[i + di, j + dj]] } else { 0.0 };
+    let low = if i >= di && j >= dj { grid[[i - di, j - dj]] } else { 0.0 };
+    (high - low) / (2.0 * h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Charge, compute_field_magnetostatic_direct};
+    use crate::builder::{FieldConfigurationBuilder, SolverKind};
+    use crate::softening::SofteningKernel;
+
+    /// Fields nearer than this to a current are not compared, where the softening on the grid, at least two cells,
+    /// differs from that of the direct solver
+    const MIN_DISTANCE: f64 = 40.0;
+
+    fn empty_configuration() -> FieldConfiguration {
+        FieldConfigurationBuilder::new(500.0, 500.0, 128, 128)
+            .boundary_width(0)
+            .solver(SolverKind::MagnetostaticMultigrid)
+            .force_law(ForceLaw::Logarithmic)
+            .softening_kernel(SofteningKernel::Plummer { length: 5.0 })
+            .try_build()
+            .unwrap()
+    }
+
+    /// Four currents at cell centroids around the middle of the grid, with no net current or dipole moment so that
+    /// the edges of the grid have little effect on the field inside
+    fn configuration() -> FieldConfiguration {
+        let mut config = empty_configuration();
+        let cells = [(48, 64, 1.0), (80, 64, 1.0), (64, 48, -1.0), (64, 80, -1.0)];
+        config.charges = cells.iter().map(|&(i, j, current)| {
+            let (x, y) = config.geometry.cell_to_centroid(i, j);
+            Charge::new(x, y, current)
+        }).collect();
+        config.charges_changed();
+        config
+    }
+
+    /// Check the field from the vector potential against the Biot-Savart law over the middle half of the grid, away
+    /// from the currents, to within a fraction of the largest field compared
+    fn assert_matches_direct(config: &FieldConfiguration, tolerance: f64) {
+        let mut largest_field: f64 = 0.0;
+        let mut largest_error: f64 = 0.0;
+        for i in 32..96 {
+            for j in 32..96 {
+                let (x, y) = config.geometry.cell_to_centroid(i, j);
+                if config.charges.iter().any(|charge| (x - charge.x).hypot(y - charge.y) < MIN_DISTANCE) {
+                    continue;
+                }
+                let grid = config.vector_potential_field(x, y).unwrap();
+                let direct = compute_field_magnetostatic_direct(config, x, y);
+                largest_field = largest_field.max(direct.u.hypot(direct.v));
+                largest_error = largest_error.max((grid.u - direct.u).hypot(grid.v - direct.v));
+            }
+        }
+        assert!(largest_field > 0.0);
+        assert!(largest_error < tolerance * largest_field, "field from the vector potential differs by {} from a largest field of {}", largest_error, largest_field);
+    }
+
+    #[test]
+    fn field_matches_direct() {
+        assert_matches_direct(&configuration(), 0.03);
+    }
+
+    #[test]
+    fn field_with_uniform_field_matches_direct() {
+        let mut config = configuration();
+        config.set_uniform_field(3.0, -2.0);
+        assert_matches_direct(&config, 0.03);
+    }
+
+    // A band of current circulating anticlockwise is a solenoid, with $B_z = \mu_0 K$ inside by the right-hand rule
+    // and no field outside, where K is the current per unit length of the band
+    #[test]
+    fn current_loop_field_z_matches_solenoid() {
+        let mut config = empty_configuration();
+        let (nx, ny) = (config.geometry.nx, config.geometry.ny);
+        let (radius, width, density) = (100.0, 20.0, 0.1);
+        let mut x_density = vec![0.0; nx * ny];
+        let mut y_density = vec![0.0; nx * ny];
+        for i in 0..nx {
+            for j in 0..ny {
+                let (x, y) = config.geometry.cell_to_centroid(i, j);
+                let (dx, dy) = (x - 250.0, y - 250.0);
+                let r = dx.hypot(dy);
+                if (r - radius).abs() < 0.5 * width {
+                    x_density[i * ny + j] = -density * dy / r;
+                    y_density[i * ny + j] = density * dx / r;
+                }
+            }
+        }
+        config.try_set_current_density(x_density, y_density, vec![]).unwrap();
+        let inside = config.units.current_field_factor() * config.units.grid_charge_scaling(ForceLaw::Logarithmic) * density * width;
+        for (x, y, expected) in [(250.0, 250.0, inside), (200.0, 270.0, inside), (300.0, 250.0, inside), (400.0, 250.0, 0.0), (250.0, 120.0, 0.0)] {
+            let field_z = config.vector_potential_field_z(x, y).unwrap();
+            assert!((field_z - expected).abs() < 0.01 * inside, "B_z {} at ({}, {}), expected {}", field_z, x, y, expected);
+        }
+    }
+}
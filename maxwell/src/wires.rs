@@ -0,0 +1,84 @@
+use serde::{Serialize, Deserialize};
+use crate::Pair;
+use crate::sources::{segment_integrals, quadrature_along_segment};
+use crate::softening::ForceLaw;
+
+/// A wire lying in the plane and carrying a steady current.
+///
+/// Unlike the out-of-plane currents represented by charges in the magnetostatic solver, in-plane currents
+/// generate a field $B_z$ perpendicular to the plane, computed directly from the Biot-Savart law. The wires are
+/// genuinely confined to the plane, so the inverse-square law applies whichever force law is chosen for charges.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WireElement {
+    /// Straight wire carrying current from (x0, y0) to (x1, y1)
+    Segment { x0: f64, y0: f64, x1: f64, y1: f64, current: f64 },
+    /// Closed polygonal loop, with the current flowing through the vertices in order
+    Loop { vertices: Vec<(f64, f64)>, current: f64 },
+    /// Circular loop, with positive current flowing anticlockwise
+    Circle { x: f64, y: f64, radius: f64, current: f64 },
+}
+
+/// A straight piece of wire as (start, end, current)
+type WirePiece = ((f64, f64), (f64, f64), f64);
+
+impl WireElement {
+    /// Break the element into straight pieces
+    pub fn pieces(&self) -> Vec<WirePiece> {
+        match self {
+            WireElement::Segment { x0, y0, x1, y1, current } => vec![((*x0, *y0), (*x1, *y1), *current)],
+            WireElement::Loop { vertices, current } => {
+                vertices.iter().zip(vertices.iter().cycle().skip(1)).map(|(a, b)| (*a, *b, *current)).collect()
+            },
+            WireElement::Circle { x, y, radius, current } => {
+                const NUM_PIECES: usize = 128;
+                let vertex = |k: usize| {
+                    let theta = 2.0 * std::f64::consts::PI * k as f64 / NUM_PIECES as f64;
+                    (x + radius * theta.cos(), y + radius * theta.sin())
+                };
+                (0..NUM_PIECES).map(|k| (vertex(k), vertex(k + 1), *current)).collect()
+            }
+        }
+    }
+
+    /// The out-of-plane field $B_z$ at (x, y), without the overall field scaling
+    pub fn field_z(&self, x: f64, y: f64, epsilon: f64) -> f64 {
+        self.pieces().iter().map(|(a, b, current)| piece_field_z(a, b, *current, x, y, epsilon)).sum()
+    }
+}
+
+/// Biot-Savart field of one straight piece, $I \int d\vec{l} \times (\vec{p}-\vec{q}) / |\vec{p}-\vec{q}|^3$
+fn piece_field_z(a: &(f64, f64), b: &(f64, f64), current: f64, x: f64, y: f64, epsilon: f64) -> f64 {
+    let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+    if length == 0.0 {
+        return 0.0;
+    }
+    let (ex, ey) = ((b.0 - a.0) / length, (b.1 - a.1) / length);
+    let Pair { u, v } = segment_integrals(x, y, *a, *b, epsilon, ForceLaw::InverseSquare).field;
+    current * (ex * v - ey * u)
+}
+
+/// The in-plane force on each wire element from the $B_z$ of all the others, $I \int d\vec{l} \times B_z \hat{z}$,
+/// without the overall field scaling.
+///
+/// Each piece of wire is integrated by Gauss-Legendre quadrature on sub-pieces no longer than the softening length,
+/// so that the field of nearby wires is resolved. Forces out of the plane, from the in-plane field of out-of-plane
+/// currents, cannot be represented and are not included.
+pub fn forces_on_wires(wires: &[WireElement], epsilon: f64) -> Vec<Pair> {
+    let field_from_others = |index: usize, x: f64, y: f64| -> f64 {
+        wires.iter().enumerate().filter(|(j, _)| *j != index).map(|(_, wire)| wire.field_z(x, y, epsilon)).sum()
+    };
+
+    wires.iter().enumerate().map(|(index, wire)| {
+        let mut force = Pair { u: 0.0, v: 0.0 };
+        for (a, b, current) in wire.pieces() {
+            let (lx, ly) = (b.0 - a.0, b.1 - a.1);
+            let integral: f64 = quadrature_along_segment(a, b, epsilon.max(1.0)).iter()
+                .map(|(x, y, weight)| weight * field_from_others(index, *x, *y)).sum();
+            // $d\vec{l} \times B_z \hat{z} = B_z (dl_y, -dl_x)$
+            force.u += current * ly * integral;
+            force.v -= current * lx * integral;
+        }
+        force
+    }).collect()
+}
@@ -1,7 +1,4 @@
-import init, { compute_field_electrostatic_direct_to_buffer, compute_field_magnetostatic_direct_to_buffer, 
-    compute_field_electrostatic_per_charge_direct_to_buffer, compute_field_magnetostatic_per_charge_direct_to_buffer, 
-    compute_electric_field_dynamic_to_buffer, init_panic_hook, FieldConfiguration, 
-    compute_forces_electrostatic, compute_forces_magnetostatic} from './maxwell/out/maxwell.js';
+import init, { init_panic_hook, FieldConfigurationBuilder, SolverKind, Solver, SolverCapability } from './maxwell/out/maxwell.js';
 
 import { draw, getChargeFromPoint } from './draw.js';
 
@@ -62,10 +59,8 @@ export async function main(params) {
 
     const dpr = window.devicePixelRatio || 1;
 
-    let computeField = compute_field_electrostatic_direct_to_buffer;
-    let computeForces = compute_forces_electrostatic;
-
     let field = null;
+    let fieldSolver = null; // the Solver for field, made along with it
 
     let dynamic = false;
 
@@ -124,15 +119,19 @@ export async function main(params) {
             animation_request_id = window.requestAnimationFrame(tickField);
     }
 
-    function solverSupport(solver) {
-        const allowPotential = solver === 'electrostatic_direct';
-        const allowForces = solver === 'electrostatic_direct' || solver === 'magnetostatic_direct';
-        const allowPerCharge = allowForces;
-        return {allowPotential, allowForces, allowPerCharge};
-    }
+    const solverKinds = {
+        'electrostatic_direct': SolverKind.ElectrostaticDirect,
+        'electrostatic_fourier': SolverKind.ElectrostaticFourier,
+        'electrostatic_multigrid': SolverKind.ElectrostaticMultigrid,
+        'dynamic': SolverKind.Dynamic,
+        'magnetostatic_direct': SolverKind.MagnetostaticDirect,
+        'magnetostatic_multigrid': SolverKind.MagnetostaticMultigrid
+    };
 
     function uiFromState() {
-        const {allowPotential, allowForces, allowPerCharge} = solverSupport(solver);
+        const allowPotential = fieldSolver.supports(SolverCapability.Potential);
+        const allowForces = fieldSolver.supports(SolverCapability.Forces);
+        const allowPerCharge = fieldSolver.supports(SolverCapability.PerCharge);
 
         if(forcesControlsDiv !== null) {
             if(allowForces) {
@@ -190,7 +189,7 @@ export async function main(params) {
 
         
 
-        if(solver === 'magnetostatic_direct') {
+        if(solver?.startsWith('magnetostatic')) {
             updateChargeOrCurrentLabel('Current');
         } else {
             updateChargeOrCurrentLabel('Charge');
@@ -209,8 +208,6 @@ export async function main(params) {
         if(solverDropdown !== null) 
             solver = solverDropdown.value;
 
-        const solverSupportInfo = solverSupport(solver);
-
         if(potentialCheckbox !== null)
             showPotential = potentialCheckbox.checked;
 
@@ -218,7 +215,7 @@ export async function main(params) {
             plotType = fieldlinesCheckbox.checked ? 'fieldline' : 'quiver';
 
         if(perChargeCheckbox !== null)
-            perCharge = solverSupportInfo.allowPerCharge && perChargeCheckbox.checked;
+            perCharge = perChargeCheckbox.checked; // cleared by updateSolverType if the solver cannot show it
 
         if(dipoleCheckbox !== null) {
             dipoleMode = dipoleCheckbox.checked;
@@ -233,32 +230,24 @@ export async function main(params) {
     function updateSolverType(is_user_update = false) {
         cic_resolution = Math.min(cic_max_resolution, Math.max(1, Math.floor(rect.width / cic_target_logical_pixel_per_cell)));
 
-        field = new FieldConfiguration(rect.width, rect.height, cic_resolution, cic_resolution);
-        
-        dynamic = solver === 'dynamic';
+        field = new FieldConfigurationBuilder(rect.width, rect.height, cic_resolution, cic_resolution)
+            .boundary_width(Math.floor(cic_resolution / 8))
+            .solver(solverKinds[solver] ?? SolverKind.ElectrostaticDirect)
+            .build();
 
-        computeForces = null;
-
-        if (solver === 'electrostatic_direct') {
-            if(perCharge) {
-                computeField = compute_field_electrostatic_per_charge_direct_to_buffer;
-            } else {
-                computeField = compute_field_electrostatic_direct_to_buffer;
-            }
-            computeForces = compute_forces_electrostatic;
-        } else if (solver === 'electrostatic_fourier' || solver === 'dynamic') {
-            computeField = compute_electric_field_dynamic_to_buffer;
-        } else if (solver === 'magnetostatic_direct') { 
-            if(perCharge) {
-                computeField = compute_field_magnetostatic_per_charge_direct_to_buffer;
-            } else { 
-                computeField = compute_field_magnetostatic_direct_to_buffer;
-            }
-            computeForces = compute_forces_magnetostatic;
-        } else {
+        if (!(solver in solverKinds)) {
             console.error('Unknown solver type');
         }
 
+        // All fields and forces are found through this one solver, until the solver type changes again
+        if(fieldSolver !== null)
+            fieldSolver.free();
+        fieldSolver = Solver.for_configuration(field);
+        
+        dynamic = solver === 'dynamic';
+
+        perCharge = perCharge && fieldSolver.supports(SolverCapability.PerCharge);
+
         uiFromState(); // e.g. updates visibility of potential checkbox
 
         if(is_user_update) {
@@ -404,17 +393,21 @@ export async function main(params) {
             dt = time_now - last_time;
         }
 
-        dt /= 5; // convert from ms to internal time units
-
-        const dx = rect.width / cic_resolution;
+        dt *= field.get_time_per_millisecond();
 
         // Try to get forward in time by dt, but abandon if it takes too long (more than 20ms)
         const performance_time_start = performance.now();
         const max_time_ms = 20.0;
 
         while (dt > 0.0 && performance.now() - performance_time_start < max_time_ms) {
-            const step = Math.min(0.1 * dx , dt);
-            field.tick(step);
+            const step = Math.min(field.max_stable_timestep(), dt);
+            try {
+                field.tick(step);
+            } catch (err) {
+                // e.g. a charge has been dragged off the grid; skip this frame rather than stopping the animation
+                console.warn(err.message);
+                break;
+            }
             dt -= step;
         }
  
@@ -437,23 +430,17 @@ export async function main(params) {
         field.set_charges(charges_no_test_charges);
         field.set_uniform_field(uniformElecX, uniformElecY);
 
-        if(!dynamic) {
-            // if dynamic, the fields are being updated in real time and we don't want to reset them
-            // if not dynamic, we need to reset the fields to trigger a recompute
-            field.reset_fields();
-        }
-
 
         let forces = null;
 
-        if(forcesCheckbox!==null && forcesCheckbox.checked && computeForces !== null) {
-            forces = computeForces(field);
+        if(forcesCheckbox!==null && forcesCheckbox.checked && fieldSolver.supports(SolverCapability.Forces)) {
+            forces = fieldSolver.forces(field);
         }
 
         extraDrawInfo['gaussianSurfacePoints'] = closeLoop(gaussianSurfacePoints);
 
-        draw(ctx, rect, charges, field, plotType, computeField, 
-            computeField === compute_field_electrostatic_direct_to_buffer && showPotential, null,
+        draw(ctx, rect, charges, field, plotType, fieldSolver, perCharge,
+            fieldSolver.supports(SolverCapability.Potential) && showPotential, null,
             forces, dipoleMode, extraDrawInfo);
 
         continueAnimation();
@@ -585,7 +572,7 @@ export async function main(params) {
         const v_values = [];
         const buffer = new Float64Array(2);
         for (let x = 0; x < rect.width; x += 10) {
-            computeField(field, x, y, buffer);
+            fieldSolver.field_to_buffer(field, x, y, buffer);
             x_values.push(x);
             u_values.push(buffer[0]);
             v_values.push(buffer[1]);
@@ -848,7 +835,7 @@ export function embed() {
             let dipoleControlsDiv = null;
             let dipoleCheckbox = null;
 
-            const labelsReferToMagnetism = (startingState && JSON.parse(startingState).solver === 'magnetostatic_direct');
+            const labelsReferToMagnetism = (startingState && JSON.parse(startingState).solver?.startsWith('magnetostatic'));
 
             if(meme.getAttribute('fieldlines-checkbox')) {
                 ({div: fieldlinesControlsDiv, checkbox: fieldlinesCheckbox} = addCheckbox('fieldlines-controls', 'Show field lines', meme));